@@ -0,0 +1,112 @@
+//! Compares streaming a large file through hyper with sending it via
+//! `sendfile(2)`. Run with `cargo bench --bench sendfile`.
+
+use hyper::server::conn::http1;
+use qsrv::{
+    responders::{FileResolver, ResolverOptions},
+    serve_zero_copy,
+};
+use std::{
+    fs,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+const FILE_SIZE: usize = 256 * 1024 * 1024;
+const ROUNDS: usize = 8;
+
+/// Serve `resolver` on an ephemeral port, with or without the zero-copy
+/// connection wrapper.
+async fn spawn_server(resolver: FileResolver, sendfile: bool) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let resolver = resolver.clone();
+            tokio::spawn(async move {
+                let builder = http1::Builder::new();
+                let _ = if sendfile {
+                    serve_zero_copy(builder, stream, resolver).await
+                } else {
+                    builder.serve_connection(stream, resolver).await
+                };
+            });
+        }
+    });
+
+    addr
+}
+
+/// Range requested by the ranged fetch, straddling several sendfile spans.
+const RANGE: (usize, usize) = (5 * 1024 * 1024 + 7, 13 * 1024 * 1024 + 3);
+
+/// Download the test file, or `range` of it, once over a fresh connection
+/// and return the response body.
+async fn fetch(addr: SocketAddr, range: Option<(usize, usize)>) -> Vec<u8> {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let range = range.map_or(String::new(), |(first, last)| format!("Range: bytes={}-{}\r\n", first, last));
+    let req = format!("GET /big.bin HTTP/1.1\r\nHost: bench\r\n{}Connection: close\r\n\r\n", range);
+    stream.write_all(req.as_bytes()).await.unwrap();
+
+    let mut res = Vec::with_capacity(FILE_SIZE + 1024);
+    stream.read_to_end(&mut res).await.unwrap();
+    let body_start = res.windows(4).position(|w| w == b"\r\n\r\n").expect("no end of headers") + 4;
+
+    res.split_off(body_start)
+}
+
+/// Fetch `range` of the file, or all of it, and check the body against the
+/// file contents; a body of the right length is not enough, as a broken
+/// sendfile path sends the right number of zeros.
+async fn fetch_checked(addr: SocketAddr, data: &[u8], range: Option<(usize, usize)>) {
+    let body = fetch(addr, range).await;
+    let expected = match range {
+        Some((first, last)) => &data[first..=last],
+        None => data,
+    };
+
+    assert_eq!(body.len(), expected.len(), "wrong body length");
+    assert!(body == expected, "body differs from the file");
+}
+
+async fn bench(name: &str, addr: SocketAddr, data: &[u8]) {
+    fetch_checked(addr, data, None).await;
+    fetch_checked(addr, data, Some(RANGE)).await;
+
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        let body = fetch(addr, None).await;
+        elapsed += start.elapsed();
+        assert!(body == data, "body differs from the file");
+    }
+
+    let mib = (FILE_SIZE * ROUNDS) as f64 / (1024.0 * 1024.0);
+    println!("{:<10} {:>8.1} ms/request {:>10.1} MiB/s",
+             name,
+             elapsed.as_secs_f64() * 1000.0 / ROUNDS as f64,
+             mib / elapsed.as_secs_f64());
+}
+
+#[tokio::main]
+async fn main() {
+    let dir = std::env::temp_dir().join(format!("qsrv-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let data: Vec<u8> = (0..FILE_SIZE).map(|i| (i * 31 % 251) as u8).collect();
+    fs::write(dir.join("big.bin"), &data).unwrap();
+
+    let resolver = FileResolver::with_options(dir.to_str().unwrap(), ResolverOptions::default()).unwrap();
+    let streamed = spawn_server(resolver.clone(), false).await;
+    let zero_copy = spawn_server(resolver, true).await;
+
+    bench("streamed", streamed, &data).await;
+    bench("sendfile", zero_copy, &data).await;
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,143 @@
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use std::path::Path;
+
+/// File names denied unless explicitly allowed.
+const DEFAULT_DENY: [&str; 4] = ["id_rsa", "id_dsa", "id_ecdsa", "id_ed25519"];
+
+/// Dot-paths that stay reachable even though hidden files are denied.
+const DEFAULT_ALLOW: [&str; 1] = [".well-known"];
+
+/// Markers that identify PEM, OpenSSH and PuTTY private keys.
+const PRIVATE_KEY_MARKERS: [&[u8]; 3] = [
+    b"PRIVATE KEY-----",
+    b"OPENSSH PRIVATE KEY",
+    b"PuTTY-User-Key-File",
+];
+
+/// Number of leading bytes inspected when looking for private keys.
+pub(crate) const PRIVATE_KEY_SNIFF_LEN: usize = 8 * 1024;
+
+/// Globs split by how they are matched: patterns without a `/` are compared
+/// with every path segment, patterns with one against the whole path. In the
+/// latter only `**` crosses a `/`.
+#[derive(Clone, Debug)]
+struct PatternSet {
+    segment: GlobSet,
+    full: GlobSet,
+}
+
+impl PatternSet {
+    fn new<S: AsRef<str>>(patterns: &[S]) -> Result<Self, globset::Error> {
+        let mut segment = GlobSetBuilder::new();
+        let mut full = GlobSetBuilder::new();
+        for pattern in patterns {
+            let pattern = pattern.as_ref();
+            if pattern.contains('/') {
+                full.add(GlobBuilder::new(pattern.trim_start_matches('/')).literal_separator(true).build()?);
+            } else {
+                segment.add(Glob::new(pattern)?);
+            }
+        }
+
+        Ok(PatternSet {
+            segment: segment.build()?,
+            full: full.build()?,
+        })
+    }
+
+    /// For each segment of `rel_path`, whether a pattern matches that segment
+    /// or a prefix of the path that ends at or after it.
+    fn matched_segments(&self, rel_path: &str) -> Vec<bool> {
+        let segments: Vec<&str> = rel_path.split('/').collect();
+        let mut matched: Vec<bool> = segments.iter().map(|segment| self.segment.is_match(segment)).collect();
+
+        let mut prefix_end = 0;
+        for (i, segment) in segments.iter().enumerate() {
+            prefix_end += segment.len() + if i > 0 { 1 } else { 0 };
+            if self.full.is_match(&rel_path[..prefix_end]) {
+                matched[..=i].iter_mut().for_each(|m| *m = true);
+            }
+        }
+
+        matched
+    }
+}
+
+/// Decides which files may be served. Refused files are answered with 404 so
+/// their existence is not revealed.
+#[derive(Clone, Debug)]
+pub struct AccessPolicy {
+    allow_hidden: bool,
+    allow: PatternSet,
+    deny: PatternSet,
+    refuse_private_keys: bool,
+}
+
+impl AccessPolicy {
+    /// Build a policy. `allow` and `deny` are globs; allowed segments bypass
+    /// both the hidden-file rule and the denylist. The built-in lists are
+    /// always included.
+    pub fn new(
+        allow_hidden: bool,
+        allow: &[String],
+        deny: &[String],
+        refuse_private_keys: bool,
+    ) -> Result<Self, globset::Error> {
+        let allow: Vec<&str> = DEFAULT_ALLOW.iter().copied().chain(allow.iter().map(String::as_str)).collect();
+        let deny: Vec<&str> = DEFAULT_DENY.iter().copied().chain(deny.iter().map(String::as_str)).collect();
+
+        Ok(AccessPolicy {
+            allow_hidden,
+            allow: PatternSet::new(&allow)?,
+            deny: PatternSet::new(&deny)?,
+            refuse_private_keys,
+        })
+    }
+
+    /// Whether the path, relative to the document root and `/`-separated,
+    /// may be served. An allow pattern only exempts the segments it matched,
+    /// so `.well-known` does not open up `.git/.well-known`.
+    pub fn permits(&self, rel_path: &str) -> bool {
+        let rel_path = rel_path.trim_matches('/');
+        if rel_path.is_empty() {
+            return true;
+        }
+
+        let allowed = self.allow.matched_segments(rel_path);
+        let denied = self.deny.matched_segments(rel_path);
+        rel_path.split('/').enumerate()
+            .filter(|(i, _)| !allowed[*i])
+            .all(|(i, segment)| !denied[i] && (self.allow_hidden || !segment.starts_with('.')))
+    }
+
+    /// Like [`AccessPolicy::permits`] for a filesystem path. Paths outside of
+    /// `root`, reached through symlinks the symlink policy allowed, are only
+    /// judged by the name they were requested under.
+    pub(crate) fn permits_path(&self, path: &Path, root: &Path) -> bool {
+        match path.strip_prefix(root) {
+            Ok(rel) => {
+                let rel: Vec<_> = rel.components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect();
+                self.permits(&rel.join("/"))
+            },
+            Err(_) => true,
+        }
+    }
+
+    pub(crate) fn refuses_private_keys(&self) -> bool {
+        self.refuse_private_keys
+    }
+}
+
+impl Default for AccessPolicy {
+    fn default() -> Self {
+        AccessPolicy::new(false, &[], &[], false).unwrap()
+    }
+}
+
+/// Whether the start of a file looks like a private key.
+pub(crate) fn looks_like_private_key(head: &[u8]) -> bool {
+    PRIVATE_KEY_MARKERS.iter()
+        .any(|marker| head.windows(marker.len()).any(|w| w == *marker))
+}
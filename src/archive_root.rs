@@ -0,0 +1,877 @@
+use crate::{
+    access_policy,
+    conditional,
+    directory_listing::{self, ListingEntry, ListingSort},
+    embedded::EmbeddedFile,
+    encoding::{self, Encoding},
+    error_pages::{error_response, PageSource},
+    file_body::{memory_body, FileBody, Part, ResponseBody, CHUNK_SIZE},
+    file_resolver::{self, ResolverOptions},
+    markdown,
+    mount_table,
+    sniff,
+    util,
+};
+use flate2::{read::GzDecoder, Decompress, FlushDecompress, Status};
+use http_body_util::BodyExt;
+use hyper::{
+    body::{Body, Bytes, Frame, Incoming, SizeHint},
+    header::HeaderValue,
+    http::request::Parts,
+    service::Service,
+    Method, Request, Response, StatusCode,
+};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    error::Error,
+    fmt,
+    fs::File,
+    future::Future,
+    io::{self, ErrorKind, Read, SeekFrom},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use time::{Date, Month};
+use tokio::{
+    fs,
+    io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, ReadBuf},
+};
+use tracing::{error, trace, warn};
+use zip::{CompressionMethod, ZipArchive};
+
+/// Most a compressed tarball may extract to, since it is held in memory.
+const MAX_IN_MEMORY: u64 = 256 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    fn of(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Where the contents of an archived file are found.
+enum Contents {
+    /// Uncompressed, at `offset` in the archive
+    Stored { offset: u64 },
+    /// A raw deflate stream of `len` bytes at `offset` in the archive
+    Deflated { offset: u64, len: u64 },
+    /// Extracted while loading, for archives that cannot be read at an offset
+    Memory(Bytes),
+}
+
+struct ArchivedFile {
+    size: u64,
+    modified: Option<SystemTime>,
+    contents: Contents,
+    /// Worked out ahead of time for embedded files
+    etag: Option<&'static str>,
+    mime: Option<&'static str>,
+    encoded: Vec<(Encoding, Bytes)>,
+}
+
+impl ArchivedFile {
+    fn new(size: u64, modified: Option<SystemTime>, contents: Contents) -> Self {
+        ArchivedFile {
+            size,
+            modified,
+            contents,
+            etag: None,
+            mime: None,
+            encoded: Vec::new(),
+        }
+    }
+}
+
+enum Node {
+    Dir,
+    File(ArchivedFile),
+}
+
+/// The contents of an archived file, ready to be served.
+enum Source {
+    /// The archive, with the file starting at `offset`
+    Disk(fs::File, u64),
+    /// The archive, with a raw deflate stream of `len` bytes at `offset`
+    Deflated(fs::File, u64, u64),
+    Memory(Bytes),
+}
+
+/// Index of an archive, keyed by the path of each entry without leading or
+/// trailing slashes. The root directory is the empty string.
+struct Archive {
+    path: PathBuf,
+    /// Size and modification time of the archive when it was indexed
+    len: u64,
+    modified: Option<SystemTime>,
+    nodes: BTreeMap<String, Node>,
+}
+
+/// The archive on disk was replaced or modified after it was indexed.
+#[derive(Debug)]
+struct ArchiveChanged;
+
+impl fmt::Display for ArchiveChanged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "archive changed since it was indexed; restart to serve the new contents")
+    }
+}
+
+impl Error for ArchiveChanged {}
+
+/// Normalize the name of an archive entry, refusing names that would escape
+/// the archive.
+fn entry_key(name: &str) -> Option<String> {
+    let mut segments = Vec::new();
+    for segment in name.split('/') {
+        match segment {
+            "" | "." => (),
+            ".." => return None,
+            s if s.contains('\\') || s.contains('\0') => return None,
+            s => segments.push(s),
+        }
+    }
+
+    Some(segments.join("/"))
+}
+
+/// Convert the MS-DOS timestamp of a zip entry, taken to be UTC.
+fn zip_time(dt: zip::DateTime) -> Option<SystemTime> {
+    let date = Date::from_calendar_date(dt.year() as i32, Month::try_from(dt.month()).ok()?, dt.day()).ok()?;
+    let time = date.with_hms(dt.hour(), dt.minute(), dt.second()).ok()?;
+
+    Some(time.assume_utc().into())
+}
+
+impl Archive {
+    fn load(path: &Path) -> io::Result<Self> {
+        let kind = ArchiveKind::of(path)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "not a .zip, .tar or .tar.gz archive"))?;
+        let file = File::open(path)?;
+        let meta = file.metadata()?;
+
+        let mut archive = Archive {
+            path: path.to_path_buf(),
+            len: meta.len(),
+            modified: meta.modified().ok(),
+            nodes: BTreeMap::new(),
+        };
+        archive.nodes.insert(String::new(), Node::Dir);
+        match kind {
+            ArchiveKind::Zip => archive.read_zip(file)?,
+            ArchiveKind::Tar => archive.read_tar(file, false)?,
+            ArchiveKind::TarGz => archive.read_tar(GzDecoder::new(file), true)?,
+        }
+
+        Ok(archive)
+    }
+
+    /// Index files embedded in the binary. Their contents are all in memory,
+    /// so there is no archive on disk to read from.
+    fn embedded(files: &'static [EmbeddedFile]) -> Self {
+        let mut archive = Archive {
+            path: PathBuf::new(),
+            len: 0,
+            modified: None,
+            nodes: BTreeMap::new(),
+        };
+        archive.nodes.insert(String::new(), Node::Dir);
+
+        for file in files {
+            let key = match entry_key(file.path) {
+                Some(key) => key,
+                None => continue,
+            };
+            let modified = file.modified.map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+            archive.add_file(key, ArchivedFile {
+                etag: Some(file.etag),
+                mime: Some(file.mime),
+                encoded: file.variants().into_iter()
+                    .map(|(enc, data)| (enc, Bytes::from_static(data)))
+                    .collect(),
+                ..ArchivedFile::new(file.data.len() as u64, modified, Contents::Memory(Bytes::from_static(file.data)))
+            });
+        }
+
+        archive
+    }
+
+    fn read_zip(&mut self, file: File) -> io::Result<()> {
+        let mut zip = ZipArchive::new(file)?;
+        for i in 0..zip.len() {
+            let entry = zip.by_index_raw(i)?;
+            let key = match entry_key(entry.name()) {
+                Some(key) => key,
+                None => {
+                    warn!("skipping archive entry {:?}: unsafe name", entry.name());
+                    continue;
+                },
+            };
+            if entry.is_dir() {
+                self.add_dir(&key);
+                continue;
+            }
+            if entry.encrypted() || entry.is_symlink() {
+                warn!("skipping archive entry {:?}: encrypted or a symlink", entry.name());
+                continue;
+            }
+
+            let contents = match entry.compression() {
+                CompressionMethod::Stored => Contents::Stored { offset: entry.data_start() },
+                CompressionMethod::Deflated => Contents::Deflated {
+                    offset: entry.data_start(),
+                    len: entry.compressed_size(),
+                },
+                method => {
+                    warn!("skipping archive entry {:?}: unsupported compression {}", entry.name(), method);
+                    continue;
+                },
+            };
+            self.add_file(key, ArchivedFile::new(entry.size(), entry.last_modified().and_then(zip_time), contents));
+        }
+
+        Ok(())
+    }
+
+    /// Index a tarball. Entries of compressed tarballs are kept in memory
+    /// since they cannot be read at an offset later, up to
+    /// [`MAX_IN_MEMORY`] in all.
+    fn read_tar<R: Read>(&mut self, reader: R, in_memory: bool) -> io::Result<()> {
+        let mut tar = tar::Archive::new(reader);
+        let mut loaded = 0;
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().into_owned();
+            let key = match entry_key(&name) {
+                Some(key) => key,
+                None => {
+                    warn!("skipping archive entry {:?}: unsafe name", name);
+                    continue;
+                },
+            };
+
+            let entry_type = entry.header().entry_type();
+            if entry_type.is_dir() {
+                self.add_dir(&key);
+                continue;
+            }
+            if !entry_type.is_file() {
+                trace!("skipping archive entry {:?}: not a regular file", name);
+                continue;
+            }
+
+            let modified = entry.header().mtime().ok().map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+            let contents = if in_memory {
+                loaded += entry.size();
+                if loaded > MAX_IN_MEMORY {
+                    return Err(io::Error::new(ErrorKind::InvalidData, format!(
+                        "compressed tarball holds more than {} MiB; serve it as a .tar or .zip instead",
+                        MAX_IN_MEMORY / (1024 * 1024))));
+                }
+                let mut data = Vec::with_capacity(entry.size() as usize);
+                entry.read_to_end(&mut data)?;
+                Contents::Memory(data.into())
+            } else {
+                Contents::Stored { offset: entry.raw_file_position() }
+            };
+            self.add_file(key, ArchivedFile::new(entry.size(), modified, contents));
+        }
+
+        Ok(())
+    }
+
+    fn add_dir(&mut self, key: &str) {
+        for (idx, _) in key.match_indices('/') {
+            self.nodes.entry(key[..idx].to_string()).or_insert(Node::Dir);
+        }
+        self.nodes.entry(key.to_string()).or_insert(Node::Dir);
+    }
+
+    fn add_file(&mut self, key: String, file: ArchivedFile) {
+        if key.is_empty() {
+            return;
+        }
+        if let Some((parent, _)) = key.rsplit_once('/') {
+            self.add_dir(parent);
+        }
+        self.nodes.insert(key, Node::File(file));
+    }
+
+    fn node(&self, key: &str) -> Option<&Node> {
+        self.nodes.get(key)
+    }
+
+    fn file(&self, key: &str) -> Option<&ArchivedFile> {
+        match self.nodes.get(key) {
+            Some(Node::File(file)) => Some(file),
+            _ => None,
+        }
+    }
+
+    /// The entries directly inside the directory `dir`, as (key, name, node).
+    fn children<'a>(&'a self, dir: &str) -> impl Iterator<Item = (&'a str, &'a str, &'a Node)> + 'a {
+        let prefix = if dir.is_empty() { String::new() } else { format!("{}/", dir) };
+        let start = prefix.len();
+        self.nodes.range(prefix.clone()..)
+            .take_while(move |(key, _)| key.starts_with(&prefix))
+            .filter_map(move |(key, node)| {
+                let name = &key[start..];
+                (!name.is_empty() && !name.contains('/')).then_some((key.as_str(), name, node))
+            })
+    }
+
+    /// Open the archive to read entries from, refusing it if it is no longer
+    /// the one that was indexed: the offsets in the index would point at the
+    /// wrong bytes.
+    async fn open(&self) -> io::Result<fs::File> {
+        let file = fs::File::open(&self.path).await?;
+        let meta = file.metadata().await?;
+        if meta.len() != self.len || meta.modified().ok() != self.modified {
+            return Err(io::Error::other(ArchiveChanged));
+        }
+
+        Ok(file)
+    }
+
+    async fn source(&self, file: &ArchivedFile) -> io::Result<Source> {
+        match &file.contents {
+            Contents::Stored { offset } => Ok(Source::Disk(self.open().await?, *offset)),
+            Contents::Deflated { offset, len } => Ok(Source::Deflated(self.open().await?, *offset, *len)),
+            Contents::Memory(data) => Ok(Source::Memory(data.clone())),
+        }
+    }
+}
+
+impl Source {
+    async fn head(&mut self, len: usize) -> io::Result<Bytes> {
+        if len == 0 {
+            return Ok(Bytes::new());
+        }
+
+        match self {
+            Source::Disk(file, offset) => {
+                file.seek(SeekFrom::Start(*offset)).await?;
+                let mut head = Vec::with_capacity(len);
+                (&mut *file).take(len as u64).read_to_end(&mut head).await?;
+                Ok(head.into())
+            },
+            Source::Deflated(file, offset, compressed_len) => {
+                let file = file.try_clone().await?;
+                let body = InflateBody::new(file, *offset, *compressed_len, vec![Part::File { start: 0, len: len as u64 }]);
+                Ok(body.collect().await?.to_bytes())
+            },
+            Source::Memory(data) => Ok(data.slice(..len.min(data.len()))),
+        }
+    }
+
+    fn body(self, parts: Vec<Part>) -> ResponseBody {
+        match self {
+            Source::Disk(file, offset) => {
+                let parts = parts.into_iter()
+                    .map(|part| match part {
+                        Part::File { start, len } => Part::File { start: start + offset, len },
+                        part => part,
+                    })
+                    .collect();
+                FileBody::new(file, parts).boxed()
+            },
+            Source::Deflated(file, offset, len) => InflateBody::new(file, offset, len, parts).boxed(),
+            Source::Memory(data) => memory_body(&data, parts),
+        }
+    }
+}
+
+enum InflateState {
+    Start,
+    Seeking,
+    Inflating,
+}
+
+/// Inflates a deflated archive entry as it is streamed, sending `parts` of
+/// the inflated contents. Like [`FileBody`], compressed data is only read
+/// when hyper polls for it. Whatever comes before a part is inflated and
+/// dropped, and parts that go back start over from the beginning.
+struct InflateBody {
+    file: fs::File,
+    /// The deflate stream in the archive
+    offset: u64,
+    len: u64,
+    inflater: Decompress,
+    state: InflateState,
+    /// Compressed bytes read so far, and how much of them has been inflated
+    input: Vec<u8>,
+    consumed: usize,
+    read: u64,
+    parts: VecDeque<Part>,
+    remaining: u64,
+}
+
+impl InflateBody {
+    fn new(file: fs::File, offset: u64, len: u64, parts: Vec<Part>) -> Self {
+        let remaining = parts.iter().map(Part::len).sum();
+
+        InflateBody {
+            file,
+            offset,
+            len,
+            inflater: Decompress::new(false),
+            state: InflateState::Start,
+            input: Vec::new(),
+            consumed: 0,
+            read: 0,
+            parts: parts.into(),
+            remaining,
+        }
+    }
+}
+
+impl Body for InflateBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+
+        loop {
+            let (start, len) = match this.parts.front() {
+                None => return Poll::Ready(None),
+                Some(Part::Bytes(b)) => {
+                    let b = b.clone();
+                    this.parts.pop_front();
+                    this.remaining -= b.len() as u64;
+                    return Poll::Ready(Some(Ok(Frame::data(b))));
+                },
+                Some(Part::File { len: 0, .. }) => {
+                    this.parts.pop_front();
+                    continue;
+                },
+                Some(Part::File { start, len }) => (*start, *len),
+            };
+            let pos = this.inflater.total_out();
+            if pos > start {
+                this.state = InflateState::Start;
+            }
+
+            match this.state {
+                InflateState::Start => {
+                    Pin::new(&mut this.file).start_seek(SeekFrom::Start(this.offset))?;
+                    this.inflater.reset(false);
+                    this.input.clear();
+                    this.consumed = 0;
+                    this.read = 0;
+                    this.state = InflateState::Seeking;
+                    continue;
+                },
+                InflateState::Seeking => {
+                    ready!(Pin::new(&mut this.file).poll_complete(cx))?;
+                    this.state = InflateState::Inflating;
+                    continue;
+                },
+                InflateState::Inflating => (),
+            }
+
+            if this.consumed == this.input.len() && this.read < this.len {
+                let want = (this.len - this.read).min(CHUNK_SIZE as u64) as usize;
+                this.input.resize(want, 0);
+                let mut read_buf = ReadBuf::new(&mut this.input);
+                let polled = Pin::new(&mut this.file).poll_read(cx, &mut read_buf);
+
+                // Keep only what was read, so a pending read is retried
+                let filled = read_buf.filled().len();
+                this.input.truncate(filled);
+                this.consumed = 0;
+                ready!(polled)?;
+                if filled == 0 {
+                    return Poll::Ready(Some(Err(io::Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "archive shrank while being served"))));
+                }
+                this.read += filled as u64;
+            }
+
+            // Inflate up to the start of the part, then the part itself
+            let want = if pos < start { start - pos } else { len };
+            let mut out = Vec::with_capacity(want.min(CHUNK_SIZE as u64) as usize);
+            let total_in = this.inflater.total_in();
+            let status = this.inflater.decompress_vec(&this.input[this.consumed..], &mut out, FlushDecompress::None)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+            this.consumed += (this.inflater.total_in() - total_in) as usize;
+
+            let exhausted = this.consumed == this.input.len() && this.read >= this.len;
+            if out.is_empty() && (status == Status::StreamEnd || exhausted) {
+                return Poll::Ready(Some(Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "archive entry is shorter than its size"))));
+            }
+            if pos < start || out.is_empty() {
+                continue;
+            }
+
+            let sent = out.len() as u64;
+            if sent == len {
+                this.parts.pop_front();
+            } else if let Some(part) = this.parts.front_mut() {
+                *part = Part::File { start: start + sent, len: len - sent };
+            }
+            this.remaining -= sent;
+            return Poll::Ready(Some(Ok(Frame::data(out.into()))));
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.remaining == 0
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining)
+    }
+}
+
+/// Serves the files of a `.zip`, `.tar` or `.tar.gz` archive without
+/// extracting it, with the same index, listing and redirect behaviour as a
+/// [`FileResolver`](crate::responders::FileResolver). The archive is indexed
+/// once, when the resolver is created; `.tar.gz` archives cannot be read at
+/// an offset, so they are extracted into memory then, up to 256 MiB.
+#[derive(Clone)]
+pub struct ArchiveResolver {
+    archive: Arc<Archive>,
+    options: Arc<ResolverOptions>,
+    /// URL prefix the resolver is mounted at, without a trailing slash
+    pub(crate) prefix: String,
+}
+
+impl ArchiveResolver {
+    /// Whether `path` has the extension of an archive this resolver reads.
+    pub fn is_archive(path: &Path) -> bool {
+        ArchiveKind::of(path).is_some()
+    }
+
+    pub fn new(path: &str) -> Result<Self, Box<dyn Error>> {
+        ArchiveResolver::with_options(path, ResolverOptions::default())
+    }
+
+    pub fn with_options(path: &str, options: ResolverOptions) -> Result<Self, Box<dyn Error>> {
+        let archive = Archive::load(Path::new(path))?;
+        trace!("indexed {} archive entries", archive.nodes.len());
+
+        Ok(ArchiveResolver {
+            archive: Arc::new(archive),
+            options: Arc::new(options),
+            prefix: String::new(),
+        })
+    }
+
+    pub(crate) fn embedded(files: &'static [EmbeddedFile], options: ResolverOptions) -> Self {
+        ArchiveResolver {
+            archive: Arc::new(Archive::embedded(files)),
+            options: Arc::new(options),
+            prefix: String::new(),
+        }
+    }
+}
+
+/// Error documents are looked up among the archive entries, as they would be
+/// in the document root of an extracted copy.
+impl PageSource for Archive {
+    async fn read_page(&self, page: &str) -> io::Result<(Vec<u8>, PathBuf)> {
+        let key = entry_key(page)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "invalid error document name"))?;
+        let file = self.file(&key).ok_or_else(|| io::Error::new(ErrorKind::NotFound, "not a file"))?;
+        let data = self.source(file).await?.head(file.size as usize).await?;
+
+        Ok((data.to_vec(), PathBuf::from(key)))
+    }
+}
+
+async fn not_found(archive: &Archive, options: &ResolverOptions) -> Response<ResponseBody> {
+    error_response(StatusCode::NOT_FOUND, archive, options).await
+}
+
+/// Resolve `path`, relative to the mount point at `prefix`.
+async fn resolve(archive: &Archive, options: &ResolverOptions, req: &Parts, prefix: &str, path: &str) -> Response<ResponseBody> {
+    if !options.access.permits(path) {
+        trace!("access policy refused {}", path);
+        return not_found(archive, options).await;
+    }
+
+    let key = path.trim_matches('/');
+    let url_path = format!("{}{}", prefix, path);
+
+    match archive.node(key) {
+        Some(Node::Dir) => {
+            if !path.ends_with('/') && options.slash_redirects {
+                return file_resolver::redirect(req, &format!("{}/", url_path));
+            }
+
+            let params = util::parse_query(req.uri.query().unwrap_or(""));
+            let accept = req.headers.get("Accept").and_then(|v| v.to_str().ok());
+            let want_json = directory_listing::wants_json(accept, &params);
+            let readme = options.markdown.then(|| markdown::README.to_string());
+            let index = options.index_files.iter().chain(&readme)
+                .map(|name| if key.is_empty() { name.clone() } else { format!("{}/{}", key, name) })
+                .find(|index_key| archive.file(index_key).is_some());
+
+            match index {
+                Some(index_key) if !(options.autoindex && want_json) => {
+                    trace!("requested directory - serving index");
+                    serve(archive, &index_key, options, req).await
+                },
+                _ if options.autoindex => {
+                    trace!("requested directory without index - serving listing");
+                    let sort = ListingSort::from_query(&params);
+                    if want_json {
+                        let depth = directory_listing::json_depth(&params);
+                        let json = directory_listing::listing_json(&url_path, depth, entries_json(archive, options, key, &sort, depth));
+                        return file_resolver::listing_response(options, path, "application/json", json);
+                    }
+
+                    let mut entries = listing_entries(archive, options, key);
+                    directory_listing::sort_entries(&mut entries, &sort);
+                    let html = directory_listing::render_html(&url_path, &entries, &sort);
+
+                    file_resolver::listing_response(options, path, "text/html; charset=utf-8", html)
+                },
+                _ => not_found(archive, options).await,
+            }
+        },
+        Some(Node::File(_)) if path.ends_with('/') => {
+            if options.slash_redirects {
+                file_resolver::redirect(req, &format!("{}/{}", prefix, key))
+            } else {
+                not_found(archive, options).await
+            }
+        },
+        Some(Node::File(_)) => serve(archive, key, options, req).await,
+        None => {
+            let html_key = format!("{}.html", key);
+            if options.clean_urls && !key.is_empty() && archive.file(&html_key).is_some() {
+                if !path.ends_with('/') {
+                    return serve(archive, &html_key, options, req).await;
+                }
+                if options.slash_redirects {
+                    return file_resolver::redirect(req, &format!("{}/{}", prefix, key));
+                }
+                return not_found(archive, options).await;
+            }
+
+            if let Some(fallback) = &options.spa_fallback {
+                let fallback = fallback.trim_matches('/');
+                if file_resolver::is_navigation(req, path) && archive.file(fallback).is_some() {
+                    trace!("serving SPA fallback document: {}", fallback);
+                    return serve(archive, fallback, options, req).await;
+                }
+            }
+
+            not_found(archive, options).await
+        },
+    }
+}
+
+/// The entries of the directory `dir` that the access policy lets through.
+fn listing_entries(archive: &Archive, options: &ResolverOptions, dir: &str) -> Vec<ListingEntry> {
+    archive.children(dir)
+        .filter(|(key, _, _)| options.access.permits(&format!("/{}", key)))
+        .map(|(key, name, node)| {
+            let (is_dir, size, modified) = match node {
+                Node::Dir => (true, 0, archive.modified),
+                Node::File(file) => (false, file.size, file.modified.or(archive.modified)),
+            };
+
+            ListingEntry {
+                name: name.to_string(),
+                mime: options.mime_types.content_type(Path::new(name), b"", false),
+                path: PathBuf::from(key),
+                is_dir,
+                size,
+                modified,
+            }
+        })
+        .collect()
+}
+
+fn entries_json(archive: &Archive, options: &ResolverOptions, dir: &str, sort: &ListingSort, depth: u32) -> Vec<Value> {
+    let mut entries = listing_entries(archive, options, dir);
+    directory_listing::sort_entries(&mut entries, sort);
+
+    entries.iter()
+        .map(|entry| {
+            let mut value = directory_listing::entry_json(entry);
+            if entry.is_dir && depth > 1 {
+                let key = entry.path.to_string_lossy();
+                value["entries"] = Value::Array(entries_json(archive, options, &key, sort, depth - 1));
+            }
+            value
+        })
+        .collect()
+}
+
+async fn serve(archive: &Archive, key: &str, options: &ResolverOptions, req: &Parts) -> Response<ResponseBody> {
+    match serve_file(archive, key, options, req).await {
+        Ok(res) => res,
+        Err(e) if e.get_ref().is_some_and(|inner| inner.is::<ArchiveChanged>()) => {
+            error!("Failed to read {} from {:?}: {}", key, archive.path.as_os_str(), e);
+            error_response(StatusCode::SERVICE_UNAVAILABLE, archive, options).await
+        },
+        Err(e) => {
+            match e.kind() {
+                ErrorKind::NotFound => trace!("refusing to serve {}: {}", key, e),
+                _ => error!("Failed to read {} from the archive: {}", key, e),
+            }
+            not_found(archive, options).await
+        },
+    }
+}
+
+/// Serve the archived file at `key`. Ranges are answered from the archive
+/// directly for stored entries and by inflating up to them otherwise.
+async fn serve_file(archive: &Archive, key: &str, options: &ResolverOptions, req: &Parts) -> io::Result<Response<ResponseBody>> {
+    let file = archive.file(key).ok_or_else(|| io::Error::new(ErrorKind::NotFound, "not a file"))?;
+    let path = Path::new(key);
+
+    // Only as much as the checks below need is read before the validators
+    // are evaluated; the rest is streamed, and inflated, once a body is sent
+    let mut head_len = 0;
+    if options.access.refuses_private_keys() {
+        head_len = access_policy::PRIVATE_KEY_SNIFF_LEN;
+    }
+    if file.mime.is_none() && options.mime_types.lookup(path).map_or(options.sniff, |mime| mime.starts_with("text/")) {
+        head_len = head_len.max(sniff::CHARSET_SNIFF_LEN);
+    }
+    let mut source = archive.source(file).await?;
+    let head = source.head((head_len as u64).min(file.size) as usize).await?;
+    if options.access.refuses_private_keys() && access_policy::looks_like_private_key(&head) {
+        return Err(io::Error::new(ErrorKind::NotFound, "file looks like a private key"));
+    }
+
+    let modified = file.modified.or(archive.modified);
+    if options.markdown && markdown::is_markdown(path) && markdown::wants_rendered(req) && file.size <= markdown::MAX_SOURCE_LEN {
+        let text = source.head(file.size as usize).await?;
+        let etag = match file.etag {
+            Some(etag) => etag.to_string(),
+            None => conditional::weak_etag(file.size, modified),
+        };
+        return Ok(markdown::rendered_response(&text, key, &etag, modified, options, req));
+    }
+
+    let mime = match file.mime {
+        Some(mime) => mime.to_string(),
+        None => options.mime_types.content_type(path, &head, options.sniff),
+    };
+
+    let accept_encoding = req.headers.get("Accept-Encoding").and_then(|v| v.to_str().ok());
+    let content_encoding = encoding::accepted_encodings(accept_encoding).into_iter()
+        .find_map(|enc| file.encoded.iter().find(|(e, _)| *e == enc));
+
+    let mut total = file.size;
+    let mut etag = match file.etag {
+        Some(etag) => etag.to_string(),
+        None => conditional::weak_etag(total, modified),
+    };
+    if let Some((enc, data)) = content_encoding {
+        trace!("serving {} variant of {}", enc.token(), key);
+        source = Source::Memory(data.clone());
+        total = data.len() as u64;
+        etag = conditional::encoded_etag(&etag, enc.token());
+    }
+
+    let validators = conditional::Validators {
+        etag,
+        last_modified: modified,
+    };
+
+    let mut builder = Response::builder();
+    if !file.encoded.is_empty() {
+        builder = builder.header("Vary", "Accept-Encoding");
+    }
+    if options.markdown && markdown::is_markdown(path) {
+        // Browsers get the rendered page instead
+        builder = builder.header("Vary", "Accept");
+    }
+    if let Some((enc, _)) = content_encoding {
+        builder = builder.header("Content-Encoding", enc.token());
+    }
+    if let Some(directives) = options.cache.directives(key, &mime) {
+        builder = builder.header("Cache-Control", directives);
+    }
+
+    file_resolver::entity_response(builder, req, &validators, total, mime, |parts| Ok(source.body(parts)))
+}
+
+/// Dispatch on the request method, then resolve the path for GET and HEAD.
+/// Returns the response and the decoded path to log.
+async fn respond(archive: &Archive, options: &ResolverOptions, req: &Parts, prefix: &str) -> (Response<ResponseBody>, String) {
+    let raw_path = req.uri.path().to_string();
+
+    if let Some(res) = file_resolver::method_response(&req.method, archive, options).await {
+        return (res, raw_path);
+    }
+
+    let (res, path) = match util::normalize_request_path(&raw_path, options.allow_encoded_slashes) {
+        Ok(path) => {
+            let res = match mount_table::mount_relative(&path, prefix) {
+                Some("") if options.slash_redirects => file_resolver::redirect(req, &format!("{}/", prefix)),
+                Some("") => resolve(archive, options, req, prefix, "/").await,
+                Some(rel) => resolve(archive, options, req, prefix, rel).await,
+                None => not_found(archive, options).await,
+            };
+            (res, path)
+        },
+        Err(e) => {
+            trace!("rejected request path: {:?}", e);
+            (error_response(StatusCode::BAD_REQUEST, archive, options).await, raw_path)
+        },
+    };
+
+    if req.method == Method::HEAD {
+        return (file_resolver::without_body(res), path);
+    }
+
+    (res, path)
+}
+
+impl Service<Request<Incoming>> for ArchiveResolver {
+    type Response = Response<ResponseBody>;
+    type Error = hyper::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn call(&mut self, req: Request<Incoming>) -> Self::Future {
+        let archive = Arc::clone(&self.archive);
+        let options = Arc::clone(&self.options);
+        let prefix = self.prefix.clone();
+        let (parts, _body) = req.into_parts();
+
+        Box::pin(async move {
+            let (mut res, path) = respond(&archive, &options, &parts, &prefix).await;
+            if options.cache.no_store() {
+                res.headers_mut().insert("Cache-Control", HeaderValue::from_static("no-store"));
+            }
+            if !options.sniff {
+                res.headers_mut().insert("X-Content-Type-Options", HeaderValue::from_static("nosniff"));
+            }
+            file_resolver::log_response(&parts, &path, &res, "");
+
+            Ok(res)
+        })
+    }
+}
@@ -0,0 +1,162 @@
+use globset::{Glob, GlobMatcher};
+use std::path::Path;
+
+/// Directive sent for fingerprinted file names such as `app.3f9a1c.js`.
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// Directive sent when caching is switched off with `--no-cache`.
+const NO_STORE: &str = "no-store";
+
+/// Built-in MIME rules, applied after the configured ones.
+const DEFAULT_MIME_RULES: [(&str, &str); 1] = [("text/html", "no-cache")];
+
+#[derive(Clone, Debug)]
+enum Matcher {
+    /// Globs without a `/` are matched against the file name, others against
+    /// the whole path below the document root
+    Glob { glob: GlobMatcher, full_path: bool },
+    /// A MIME type such as `text/html`, or a whole top-level type (`image/*`)
+    Mime(String),
+}
+
+/// Maps a path glob or a MIME type to a `Cache-Control` value.
+#[derive(Clone, Debug)]
+pub struct CacheRule {
+    matcher: Matcher,
+    directives: String,
+}
+
+impl CacheRule {
+    /// Rule for paths matching `pattern`, e.g. `*.woff2` or `assets/**`.
+    pub fn glob(pattern: &str, directives: &str) -> Result<Self, globset::Error> {
+        let full_path = pattern.contains('/');
+        let glob = Glob::new(pattern.trim_start_matches('/'))?.compile_matcher();
+
+        Ok(CacheRule {
+            matcher: Matcher::Glob { glob, full_path },
+            directives: directives.to_string(),
+        })
+    }
+
+    /// Rule for responses of MIME type `pattern`, e.g. `text/css` or `image/*`.
+    /// Parameters such as `charset` are ignored when matching.
+    pub fn mime(pattern: &str, directives: &str) -> Self {
+        CacheRule {
+            matcher: Matcher::Mime(pattern.to_ascii_lowercase()),
+            directives: directives.to_string(),
+        }
+    }
+
+    fn matches(&self, rel_path: &str, mime: &str) -> bool {
+        match &self.matcher {
+            Matcher::Glob { glob, full_path: true } => glob.is_match(rel_path),
+            Matcher::Glob { glob, full_path: false } => glob.is_match(file_name(rel_path)),
+            Matcher::Mime(pattern) => {
+                let essence = mime.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+                match pattern.strip_suffix("/*") {
+                    Some(top) => essence.split('/').next() == Some(top),
+                    None => essence == *pattern,
+                }
+            },
+        }
+    }
+}
+
+/// Decides the `Cache-Control` header of served files. The first matching
+/// rule wins; fingerprinted file names are checked after the configured
+/// rules and before the built-in ones.
+#[derive(Clone, Debug)]
+pub struct CachePolicy {
+    rules: Vec<CacheRule>,
+    defaults: Vec<CacheRule>,
+    detect_hashed: bool,
+    no_store: bool,
+}
+
+impl CachePolicy {
+    /// Build a policy. With `no_store` every response is sent with
+    /// `no-store` and the rules are ignored.
+    pub fn new(rules: Vec<CacheRule>, detect_hashed: bool, no_store: bool) -> Self {
+        CachePolicy {
+            rules,
+            defaults: DEFAULT_MIME_RULES.iter()
+                .map(|(pattern, directives)| CacheRule::mime(pattern, directives))
+                .collect(),
+            detect_hashed,
+            no_store,
+        }
+    }
+
+    /// The `Cache-Control` value for a path relative to the document root,
+    /// `/`-separated, served as `mime`.
+    pub fn directives(&self, rel_path: &str, mime: &str) -> Option<&str> {
+        if self.no_store {
+            return Some(NO_STORE);
+        }
+        let rel_path = rel_path.trim_matches('/');
+
+        if let Some(rule) = self.rules.iter().find(|r| r.matches(rel_path, mime)) {
+            return Some(rule.directives.as_str());
+        }
+        if self.detect_hashed && is_hashed_name(file_name(rel_path)) {
+            return Some(IMMUTABLE);
+        }
+
+        self.defaults.iter()
+            .find(|r| r.matches(rel_path, mime))
+            .map(|r| r.directives.as_str())
+    }
+
+    /// Like [`CachePolicy::directives`] for a filesystem path. Paths outside
+    /// of `root` are matched by their file name only.
+    pub(crate) fn directives_for_path(&self, path: &Path, root: &Path, mime: &str) -> Option<&str> {
+        let rel = path.strip_prefix(root).unwrap_or_else(|_| Path::new(path.file_name().unwrap_or_default()));
+        let rel: Vec<_> = rel.components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+
+        self.directives(&rel.join("/"), mime)
+    }
+
+    /// Whether every response is marked `no-store`.
+    pub(crate) fn no_store(&self) -> bool {
+        self.no_store
+    }
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        CachePolicy::new(Vec::new(), true, false)
+    }
+}
+
+fn file_name(rel_path: &str) -> &str {
+    rel_path.rsplit('/').next().unwrap_or(rel_path)
+}
+
+/// Whether a file name carries a content fingerprint as produced by common
+/// bundlers: a `.` or `-` separated part between the stem and the extension
+/// that is either at least 6 lowercase hex digits mixing digits with `a`-`f`
+/// (`app.3f9a1c.js`, `main-0c1a9f2e.css`) or 8 to 32 alphanumeric
+/// characters mixing digits with both cases (`index-B4dE9fQa.js`). Runs of
+/// digits alone are dates or counters (`report-20240101.pdf`), not hashes.
+pub(crate) fn is_hashed_name(name: &str) -> bool {
+    let stem = match name.rsplit_once('.') {
+        Some((stem, _)) => stem,
+        None => return false,
+    };
+
+    stem.split(['.', '-']).skip(1).any(|part| {
+        let hex = part.len() >= 6
+            && part.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+            && part.bytes().any(|b| b.is_ascii_digit())
+            && part.bytes().any(|b| (b'a'..=b'f').contains(&b));
+        let mixed = (8..=32).contains(&part.len())
+            && part.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+            && part.bytes().any(|b| b.is_ascii_digit())
+            && part.bytes().any(|b| b.is_ascii_uppercase())
+            && part.bytes().any(|b| b.is_ascii_lowercase());
+
+        hex || mixed
+    })
+}
@@ -1,12 +1,185 @@
 pub use clap::{ArgAction, Parser};
+use crate::responders::SymlinkPolicy;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct CommandLine {
-    /// Document root where served files are located
+    /// Serve dotfiles and files inside dot-directories
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    pub allow_hidden: bool,
+
+    /// Glob of paths to serve even if hidden or denied; may be repeated
+    #[arg(long, value_name="GLOB")]
+    pub allow: Vec<String>,
+
+    /// Glob of paths never to serve; may be repeated
+    #[arg(long, value_name="GLOB")]
+    pub deny: Vec<String>,
+
+    /// Refuse to serve files whose contents look like private keys
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    pub refuse_private_keys: bool,
+
+    /// Treat %2F in request paths as a path separator instead of rejecting it
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    pub allow_encoded_slashes: bool,
+
+    /// Which symbolic links to follow
+    #[arg(long, value_enum, value_name="POLICY", default_value_t=SymlinkPolicy::default())]
+    pub symlinks: SymlinkPolicy,
+
+    /// Render HTML (or JSON, on request) listings for directories without an index.html
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    pub autoindex: bool,
+
+    /// Document root where served files are located; may also be a .zip,
+    /// .tar or .tar.gz archive
     #[arg(short, long)]
     pub document_root: Option<String>,
 
+    /// Serve a URL prefix from a directory or archive (e.g. /assets=../shared/assets);
+    /// may be repeated. Options may follow the path, separated by commas:
+    /// autoindex, no-autoindex and index=NAME (repeatable)
+    #[arg(long, value_name="PREFIX=PATH[,OPTION...]", value_parser=parse_mount)]
+    pub mount: Vec<SiteArg>,
+
+    /// Serve a host name, or a wildcard such as *.localhost, from a directory
+    /// (e.g. admin.localhost=../admin/dist); may be repeated. Takes the same
+    /// options as --mount. Other hosts are served from the document root and
+    /// mounts, if given
+    #[arg(long, value_name="HOST=PATH[,OPTION...]", value_parser=parse_vhost)]
+    pub vhost: Vec<SiteArg>,
+
+    /// Serve requests for unknown hosts from this --vhost instead
+    #[arg(long, value_name="HOST")]
+    pub default_host: Option<String>,
+
+    /// Compress text-like responses on the fly (gzip, brotli or zstd)
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    pub compress: bool,
+
+    /// Compression level for on-the-fly compression (clamped per codec)
+    #[arg(long)]
+    pub compression_level: Option<u32>,
+
+    /// Smallest file size in bytes that is compressed on the fly
+    #[arg(long)]
+    pub compression_min_size: Option<u64>,
+
+    /// Serve precompressed .br, .zst and .gz siblings when the client accepts them
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    pub precompressed: bool,
+
+    /// Serve a fallback document (index.html by default) for unknown
+    /// extensionless paths requested by browsers, for client-side routing
+    #[arg(long, value_name="FILE", num_args=0..=1, default_missing_value="index.html")]
+    pub spa: Option<String>,
+
+    /// Comma-separated index file names tried in order for directories
+    #[arg(long, value_name="NAMES", value_delimiter=',')]
+    pub index: Vec<String>,
+
+    /// Serve /about from about.html when /about does not exist
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    pub clean_urls: bool,
+
+    /// Do not redirect directories to a trailing slash (or files away from one)
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    pub no_slash_redirects: bool,
+
+    /// Error document for a status code, relative to the document root
+    /// (e.g. 404=errors/missing.html); may be repeated
+    #[arg(long, value_name="STATUS=FILE", value_parser=parse_error_page)]
+    pub error_page: Vec<(u16, String)>,
+
+    /// Cache-Control value for paths matching a glob
+    /// (e.g. "assets/**=max-age=3600"); may be repeated, first match wins
+    #[arg(long, value_name="GLOB=DIRECTIVES", value_parser=parse_cache_rule)]
+    pub cache_control: Vec<(String, String)>,
+
+    /// Cache-Control value for a MIME type, or a whole type such as image/*
+    /// (e.g. "text/css=max-age=600"); may be repeated, checked after --cache-control
+    #[arg(long, value_name="MIME=DIRECTIVES", value_parser=parse_cache_rule)]
+    pub cache_control_type: Vec<(String, String)>,
+
+    /// Do not mark fingerprinted file names (app.3f9a1c.js) as immutable
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    pub no_hashed_immutable: bool,
+
+    /// Send Cache-Control: no-store with every response
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    pub no_cache: bool,
+
+    /// Additional MIME types from a file in /etc/mime.types format
+    #[arg(long, value_name="FILE")]
+    pub mime_types: Option<String>,
+
+    /// MIME type for a file extension (e.g. "tar.zst=application/zstd");
+    /// may be repeated, overrides built-in and --mime-types entries
+    #[arg(long, value_name="EXT=TYPE", value_parser=parse_mime_type)]
+    pub mime_type: Vec<(String, String)>,
+
+    /// Sniff the type of files without a known extension from their contents;
+    /// without this, responses carry X-Content-Type-Options: nosniff
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    pub sniff: bool,
+
+    /// Keep small files in memory, revalidated against their modification
+    /// time and inode on every request
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    pub file_cache: bool,
+
+    /// Most files kept in the file cache [default: 1024]
+    #[arg(long, value_name="COUNT")]
+    pub file_cache_entries: Option<usize>,
+
+    /// Total bytes kept in the file cache [default: 64 MiB]
+    #[arg(long, value_name="BYTES")]
+    pub file_cache_size: Option<u64>,
+
+    /// Largest file in bytes that is cached [default: 1 MiB]
+    #[arg(long, value_name="BYTES")]
+    pub file_cache_max_file: Option<u64>,
+
+    /// Send large files with sendfile(2) on Linux instead of streaming them
+    /// through the server
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    pub sendfile: bool,
+
+    /// Smallest file in bytes sent with --sendfile [default: 1 MiB]
+    #[arg(long, value_name="BYTES", requires="sendfile")]
+    pub sendfile_min_size: Option<u64>,
+
+    /// Render .md files to HTML for browsers (?raw sends the source) and use
+    /// README.md as the index of directories without an index file; raw HTML
+    /// in documents is shown as text
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    pub markdown: bool,
+
+    /// HTML page to put rendered Markdown into, with {{title}} and {{content}}
+    /// placeholders; implies --markdown
+    #[arg(long, value_name="FILE")]
+    pub markdown_template: Option<String>,
+
+    /// Process server-side include directives (<!--#include virtual="..." -->,
+    /// <!--#echo var="..." -->) in files matching a glob (e.g. "*.shtml");
+    /// may be repeated
+    #[arg(long, value_name="GLOB")]
+    pub ssi: Vec<String>,
+
+    /// JSON file whose values server-side includes can echo as data.KEY
+    #[arg(long, value_name="FILE")]
+    pub ssi_data: Option<String>,
+
+    /// Environment variable server-side includes can echo as env.NAME; may be
+    /// repeated, other variables stay hidden
+    #[arg(long, value_name="NAME")]
+    pub ssi_env: Vec<String>,
+
+    /// Derive ETags from file contents rather than size and modification time
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    pub strong_etags: bool,
+
     // Port to listen on
     #[arg(short, long)]
     pub port: Option<u16>,
@@ -23,3 +196,81 @@ pub struct CommandLine {
     #[arg(short, long, action=ArgAction::Count, default_value="0")]
     pub verbose: u8,
 }
+
+fn parse_error_page(arg: &str) -> Result<(u16, String), String> {
+    let (status, file) = arg.split_once('=')
+        .ok_or_else(|| format!("expected STATUS=FILE, got \"{}\"", arg))?;
+    let status = status.parse::<u16>()
+        .ok()
+        .filter(|s| (400..600).contains(s))
+        .ok_or_else(|| format!("invalid error status \"{}\"", status))?;
+
+    Ok((status, file.to_string()))
+}
+
+fn parse_cache_rule(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((pattern, directives)) if !pattern.is_empty() && !directives.is_empty() => {
+            Ok((pattern.to_string(), directives.to_string()))
+        },
+        _ => Err(format!("expected PATTERN=DIRECTIVES, got \"{}\"", arg)),
+    }
+}
+
+fn parse_mime_type(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((ext, mime)) if !ext.is_empty() && mime.contains('/') => {
+            Ok((ext.to_string(), mime.to_string()))
+        },
+        _ => Err(format!("expected EXT=TYPE, got \"{}\"", arg)),
+    }
+}
+
+/// A `--mount` or `--vhost` argument: a URL prefix or host name, the
+/// directory to serve and its options.
+#[derive(Clone, Debug)]
+pub struct SiteArg {
+    pub name: String,
+    pub path: String,
+    pub autoindex: Option<bool>,
+    pub index: Vec<String>,
+}
+
+fn parse_site(arg: &str, expected: &str) -> Result<SiteArg, String> {
+    let (name, rest) = arg.split_once('=')
+        .filter(|(name, _)| !name.is_empty())
+        .ok_or_else(|| format!("expected {}, got \"{}\"", expected, arg))?;
+    let mut fields = rest.split(',');
+    let path = fields.next().filter(|p| !p.is_empty())
+        .ok_or_else(|| format!("missing path in \"{}\"", arg))?;
+
+    let mut site = SiteArg {
+        name: name.to_string(),
+        path: path.to_string(),
+        autoindex: None,
+        index: Vec::new(),
+    };
+    for field in fields {
+        match field.split_once('=') {
+            None if field == "autoindex" => site.autoindex = Some(true),
+            None if field == "no-autoindex" => site.autoindex = Some(false),
+            Some(("index", name)) if !name.is_empty() => site.index.push(name.to_string()),
+            _ => return Err(format!("unknown option \"{}\"", field)),
+        }
+    }
+
+    Ok(site)
+}
+
+fn parse_mount(arg: &str) -> Result<SiteArg, String> {
+    parse_site(arg, "/PREFIX=PATH")
+        .and_then(|site| if site.name.starts_with('/') {
+            Ok(site)
+        } else {
+            Err(format!("mount prefix must start with /, got \"{}\"", site.name))
+        })
+}
+
+fn parse_vhost(arg: &str) -> Result<SiteArg, String> {
+    parse_site(arg, "HOST=PATH")
+}
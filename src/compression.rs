@@ -0,0 +1,154 @@
+use crate::{encoding::Encoding, file_body::ResponseBody};
+use brotli::CompressorWriter;
+use flate2::{write::GzEncoder, Compression};
+use hyper::body::{Body, Bytes, Frame};
+use std::{
+    io::{self, Write},
+    mem,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+/// Files smaller than this are not worth compressing on the fly.
+pub(crate) const DEFAULT_MIN_SIZE: u64 = 1024;
+
+/// Whether responses of type `mime` benefit from compression. Formats that are
+/// already compressed (images, archives, audio and video) are excluded.
+pub(crate) fn is_compressible(mime: &str) -> bool {
+    let essence = mime.split(';').next().unwrap_or("").trim();
+
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || matches!(essence,
+            "application/json"
+            | "application/javascript"
+            | "application/xml"
+            | "application/wasm"
+            | "application/yaml"
+            | "application/toml"
+            | "application/vnd.apple.mpegurl"
+            | "image/svg+xml")
+}
+
+enum Encoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Brotli(Box<CompressorWriter<Vec<u8>>>),
+    Zstd(zstd::Encoder<'static, Vec<u8>>),
+}
+
+impl Encoder {
+    /// Create an encoder. `level` is clamped to the range each codec supports;
+    /// `None` selects a moderate default suitable for per-request compression.
+    fn new(encoding: Encoding, level: Option<u32>) -> io::Result<Self> {
+        Ok(match encoding {
+            Encoding::Gzip => {
+                let level = level.unwrap_or(6).min(9);
+                Encoder::Gzip(GzEncoder::new(Vec::new(), Compression::new(level)))
+            },
+            Encoding::Brotli => {
+                let level = level.unwrap_or(4).min(11);
+                Encoder::Brotli(Box::new(CompressorWriter::new(Vec::new(), 4096, level, 22)))
+            },
+            Encoding::Zstd => {
+                let level = level.unwrap_or(3).clamp(1, 22);
+                Encoder::Zstd(zstd::Encoder::new(Vec::new(), level as i32)?)
+            },
+        })
+    }
+
+    /// Feed `data` to the encoder and return whatever output it produced.
+    fn write(&mut self, data: &[u8]) -> io::Result<Bytes> {
+        let out = match self {
+            Encoder::Gzip(e) => {
+                e.write_all(data)?;
+                e.get_mut()
+            },
+            Encoder::Brotli(e) => {
+                e.write_all(data)?;
+                e.get_mut()
+            },
+            Encoder::Zstd(e) => {
+                e.write_all(data)?;
+                e.get_mut()
+            },
+        };
+
+        Ok(Bytes::from(mem::take(out)))
+    }
+
+    fn finish(self) -> io::Result<Bytes> {
+        let out = match self {
+            Encoder::Gzip(e) => e.finish()?,
+            Encoder::Brotli(e) => e.into_inner(),
+            Encoder::Zstd(e) => e.finish()?,
+        };
+
+        Ok(Bytes::from(out))
+    }
+}
+
+/// Compress `data` in one go, e.g. ahead of time for embedded assets.
+pub(crate) fn compress(data: &[u8], encoding: Encoding, level: Option<u32>) -> io::Result<Vec<u8>> {
+    let mut encoder = Encoder::new(encoding, level)?;
+    let mut out = encoder.write(data)?.to_vec();
+    out.extend_from_slice(&encoder.finish()?);
+
+    Ok(out)
+}
+
+/// Compresses another body frame by frame as it is streamed.
+pub(crate) struct CompressedBody {
+    inner: ResponseBody,
+    encoder: Option<Encoder>,
+}
+
+impl CompressedBody {
+    pub fn new(inner: ResponseBody, encoding: Encoding, level: Option<u32>) -> io::Result<Self> {
+        Ok(CompressedBody {
+            inner,
+            encoder: Some(Encoder::new(encoding, level)?),
+        })
+    }
+}
+
+impl Body for CompressedBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+
+        loop {
+            let encoder = match this.encoder.as_mut() {
+                Some(e) => e,
+                None => return Poll::Ready(None),
+            };
+
+            match ready!(Pin::new(&mut this.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => {
+                    let data = match frame.into_data() {
+                        Ok(d) => d,
+                        Err(_) => continue,
+                    };
+                    let out = encoder.write(&data)?;
+                    if !out.is_empty() {
+                        return Poll::Ready(Some(Ok(Frame::data(out))));
+                    }
+                },
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => {
+                    let out = this.encoder.take().unwrap().finish()?;
+                    return Poll::Ready(Some(Ok(Frame::data(out))));
+                },
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.encoder.is_none()
+    }
+}
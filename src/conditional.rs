@@ -0,0 +1,144 @@
+use hyper::{HeaderMap, Method};
+use sha2::{Digest, Sha256};
+use std::{
+    io,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+
+/// Validators describing the current representation of a resource.
+pub(crate) struct Validators {
+    pub etag: String,
+    pub last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    /// Value for the `Last-Modified` header, if the modification time is known.
+    pub fn last_modified_header(&self) -> Option<String> {
+        self.last_modified.map(httpdate::fmt_http_date)
+    }
+}
+
+/// Outcome of evaluating the request preconditions (RFC 9110, section 13.2.2).
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Precondition {
+    Proceed,
+    NotModified,
+    Failed,
+}
+
+/// Weak entity tag derived from the file size and modification time.
+pub(crate) fn weak_etag(size: u64, modified: Option<SystemTime>) -> String {
+    let nanos = modified
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+
+    format!("W/\"{:x}-{:x}\"", size, nanos)
+}
+
+/// Strong entity tag derived from a hash of the file contents. The file is
+/// read in full, so this is considerably more expensive than [`weak_etag`].
+/// The read position is left at the end of the file.
+pub(crate) async fn strong_etag(file: &mut File) -> io::Result<String> {
+    file.rewind().await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+
+    Ok(etag_from_hasher(hasher))
+}
+
+/// Like [`strong_etag`] for contents already in memory.
+pub(crate) fn strong_etag_for(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+
+    etag_from_hasher(hasher)
+}
+
+fn etag_from_hasher(hasher: Sha256) -> String {
+    let digest = hasher.finalize();
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+
+    format!("\"{}\"", hex)
+}
+
+/// Derive the entity tag of a representation encoded on the fly from the tag
+/// of the unencoded file. The result is always weak since the compressed
+/// bytes are not guaranteed to be identical between requests.
+pub(crate) fn encoded_etag(etag: &str, coding: &str) -> String {
+    let tag = opaque_tag(etag).trim_matches('"');
+
+    format!("W/\"{}-{}\"", tag, coding)
+}
+
+fn opaque_tag(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+fn strong_match(a: &str, b: &str) -> bool {
+    !a.starts_with("W/") && !b.starts_with("W/") && a == b
+}
+
+fn weak_match(a: &str, b: &str) -> bool {
+    opaque_tag(a) == opaque_tag(b)
+}
+
+/// Check an entity-tag list header (`If-Match` / `If-None-Match`) against
+/// `etag` with the given comparison function.
+fn tag_list_matches(list: &str, etag: &str, cmp: fn(&str, &str) -> bool) -> bool {
+    list.split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || cmp(tag, etag))
+}
+
+fn unix_seconds(t: SystemTime) -> Option<u64> {
+    t.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+/// Whether the representation was modified after the HTTP date in `header`.
+/// Unparseable dates are treated as if the header was absent.
+fn modified_since(header: &str, last_modified: SystemTime) -> Option<bool> {
+    let date = httpdate::parse_http_date(header.trim()).ok()?;
+
+    Some(unix_seconds(last_modified) > unix_seconds(date))
+}
+
+/// Evaluate `If-Match`, `If-Unmodified-Since`, `If-None-Match` and
+/// `If-Modified-Since` in the order RFC 9110 prescribes.
+pub(crate) fn evaluate(headers: &HeaderMap, method: &Method, validators: &Validators) -> Precondition {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let safe = method == Method::GET || method == Method::HEAD;
+
+    if let Some(if_match) = header("If-Match") {
+        if !tag_list_matches(if_match, &validators.etag, strong_match) {
+            return Precondition::Failed;
+        }
+    } else if let (Some(since), Some(modified)) = (header("If-Unmodified-Since"), validators.last_modified) {
+        if modified_since(since, modified) == Some(true) {
+            return Precondition::Failed;
+        }
+    }
+
+    if let Some(if_none_match) = header("If-None-Match") {
+        if tag_list_matches(if_none_match, &validators.etag, weak_match) {
+            return if safe { Precondition::NotModified } else { Precondition::Failed };
+        }
+    } else if let (true, Some(since), Some(modified)) = (safe, header("If-Modified-Since"), validators.last_modified) {
+        if modified_since(since, modified) == Some(false) {
+            return Precondition::NotModified;
+        }
+    }
+
+    Precondition::Proceed
+}
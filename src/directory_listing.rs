@@ -0,0 +1,337 @@
+use crate::{access_policy, file_resolver::ResolverOptions, safe_open::SafeRoot, util};
+use serde_json::{json, Value};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    time::SystemTime,
+};
+use time::{format_description::well_known::Rfc3339, macros::format_description, OffsetDateTime};
+use tokio::{fs, io::AsyncReadExt};
+
+/// Upper bound for the `depth` query parameter of JSON listings.
+const MAX_JSON_DEPTH: u32 = 8;
+
+pub(crate) struct ListingEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub mime: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SortKey {
+    Name,
+    Size,
+    Modified,
+}
+
+impl SortKey {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Size => "size",
+            SortKey::Modified => "mtime",
+        }
+    }
+}
+
+pub(crate) struct ListingSort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl ListingSort {
+    /// Read `sort` (name, size, mtime) and `order` (asc, desc) from the query
+    /// parameters, falling back to ascending by name.
+    pub fn from_query(params: &HashMap<String, String>) -> Self {
+        let key = match params.get("sort").map(String::as_str) {
+            Some("size") => SortKey::Size,
+            Some("mtime") => SortKey::Modified,
+            _ => SortKey::Name,
+        };
+        let descending = matches!(params.get("order").map(String::as_str), Some("desc"));
+
+        ListingSort { key, descending }
+    }
+}
+
+/// Read the entries of `dir`, skipping anything the symlink policy or the
+/// access policy refuses, so the listing never advertises files that would
+/// not be served. Entry paths are kept as requested, below `dir`.
+pub(crate) async fn read_entries(dir: &Path, root: &SafeRoot, options: &ResolverOptions) -> io::Result<Vec<ListingEntry>> {
+    let access = &options.access;
+    let mut entries = Vec::new();
+    let mut read_dir = fs::read_dir(dir).await?;
+
+    while let Some(entry) = read_dir.next_entry().await? {
+        let path = entry.path();
+        if !access.permits_path(&path, root.path()) {
+            continue;
+        }
+        let (file, can_path) = match root.open(&path).await {
+            Ok(opened) => opened,
+            Err(_) => continue,
+        };
+        if !access.permits_path(&can_path, root.path()) {
+            continue;
+        }
+
+        let file = fs::File::from(file);
+        let meta = match file.metadata().await {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+        if meta.is_file() && access.refuses_private_keys() && is_private_key(file).await {
+            continue;
+        }
+
+        entries.push(ListingEntry {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            mime: options.mime_types.content_type(&path, b"", false),
+            path,
+            is_dir: meta.is_dir(),
+            size: if meta.is_dir() { 0 } else { meta.len() },
+            modified: meta.modified().ok(),
+        });
+    }
+
+    Ok(entries)
+}
+
+async fn is_private_key(file: fs::File) -> bool {
+    let mut head = Vec::with_capacity(access_policy::PRIVATE_KEY_SNIFF_LEN);
+    match file.take(access_policy::PRIVATE_KEY_SNIFF_LEN as u64).read_to_end(&mut head).await {
+        Ok(_) => access_policy::looks_like_private_key(&head),
+        Err(_) => true,
+    }
+}
+
+/// Sort entries in place. Directories are always grouped before files.
+pub(crate) fn sort_entries(entries: &mut [ListingEntry], sort: &ListingSort) {
+    entries.sort_by(|a, b| {
+        let ord = match sort.key {
+            SortKey::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Modified => a.modified.cmp(&b.modified),
+        };
+        let ord = if sort.descending { ord.reverse() } else { ord };
+
+        match (a.is_dir, b.is_dir) {
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            _ => ord.then_with(|| a.name.cmp(&b.name)),
+        }
+    });
+}
+
+fn icon_for(entry: &ListingEntry) -> &'static str {
+    if entry.is_dir {
+        return "\u{1F4C1}";
+    }
+
+    let mime = &entry.mime;
+    match mime.split('/').next() {
+        Some("image") => "\u{1F5BC}",
+        Some("audio") => "\u{1F3B5}",
+        Some("video") => "\u{1F39E}",
+        Some("text") => "\u{1F4C4}",
+        _ if mime.contains("zip") || mime.contains("tar") || mime.contains("compressed") => "\u{1F4E6}",
+        _ => "\u{1F4C4}",
+    }
+}
+
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", size, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn format_modified(modified: Option<SystemTime>) -> String {
+    let fmt = format_description!("[year]-[month]-[day] [hour]:[minute]");
+    modified
+        .and_then(|m| OffsetDateTime::from(m).format(&fmt).ok())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// Render an HTML listing for the directory served at `url_path`.
+pub(crate) fn render_html(url_path: &str, entries: &[ListingEntry], sort: &ListingSort) -> String {
+    let base = if url_path.ends_with('/') {
+        url_path.to_string()
+    } else {
+        format!("{}/", url_path)
+    };
+    let title = util::html_escape(&base);
+
+    let header = |key: SortKey, label: &str| {
+        let order = if sort.key == key && !sort.descending { "desc" } else { "asc" };
+        let arrow = match (sort.key == key, sort.descending) {
+            (true, false) => " \u{25B2}",
+            (true, true) => " \u{25BC}",
+            _ => "",
+        };
+        format!("<th><a href=\"?sort={}&amp;order={}\">{}{}</a></th>", key.as_str(), order, label, arrow)
+    };
+
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str(&format!("<title>Index of {}</title>\n", title));
+    out.push_str("<style>\n\
+        body { font-family: sans-serif; margin: 2em; }\n\
+        table { border-collapse: collapse; }\n\
+        th, td { padding: 0.2em 1em; text-align: left; }\n\
+        td.size { text-align: right; }\n\
+        a { text-decoration: none; }\n\
+        </style>\n</head>\n<body>\n");
+    out.push_str(&format!("<h1>Index of {}</h1>\n<table>\n<tr><th></th>", title));
+    out.push_str(&header(SortKey::Name, "Name"));
+    out.push_str(&header(SortKey::Size, "Size"));
+    out.push_str(&header(SortKey::Modified, "Modified"));
+    out.push_str("</tr>\n");
+
+    if base != "/" {
+        let parent = match base[..base.len() - 1].rfind('/') {
+            Some(idx) => &base[..idx + 1],
+            None => "/",
+        };
+        out.push_str(&format!(
+            "<tr><td>\u{2B06}</td><td><a href=\"{}\">Parent directory</a></td><td></td><td></td></tr>\n",
+            util::html_escape(&util::percent_encode_path(parent))));
+    }
+
+    for entry in entries {
+        let mut href = format!("{}{}", util::percent_encode_path(&base), util::percent_encode_segment(&entry.name));
+        let mut name = util::html_escape(&entry.name);
+        if entry.is_dir {
+            href.push('/');
+            name.push('/');
+        }
+        let size = if entry.is_dir { "-".to_string() } else { format_size(entry.size) };
+
+        out.push_str(&format!(
+            "<tr><td>{}</td><td><a href=\"{}\">{}</a></td><td class=\"size\">{}</td><td>{}</td></tr>\n",
+            icon_for(entry),
+            util::html_escape(&href),
+            name,
+            size,
+            format_modified(entry.modified)));
+    }
+
+    out.push_str("</table>\n</body>\n</html>\n");
+
+    out
+}
+
+/// Whether the client asked for a JSON listing, either with `?format=json` or
+/// by listing `application/json` in its `Accept` header.
+pub(crate) fn wants_json(accept: Option<&str>, params: &HashMap<String, String>) -> bool {
+    if let Some(format) = params.get("format") {
+        return format == "json";
+    }
+
+    accept.is_some_and(|accept| {
+        accept.split(',').any(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let media = parts.next().unwrap_or("");
+            let rejected = parts.any(|p| p.replace(' ', "") == "q=0");
+            media.eq_ignore_ascii_case("application/json") && !rejected
+        })
+    })
+}
+
+/// Describe one entry of a JSON listing, without any nested entries.
+pub(crate) fn entry_json(entry: &ListingEntry) -> Value {
+    let modified = entry.modified
+        .and_then(|m| OffsetDateTime::from(m).format(&Rfc3339).ok());
+
+    if entry.is_dir {
+        json!({
+            "name": entry.name,
+            "type": "directory",
+            "modified": modified,
+        })
+    } else {
+        json!({
+            "name": entry.name,
+            "type": "file",
+            "size": entry.size,
+            "modified": modified,
+            "mime": entry.mime,
+        })
+    }
+}
+
+/// Number of directory levels asked for by the `depth` query parameter.
+pub(crate) fn json_depth(params: &HashMap<String, String>) -> u32 {
+    params.get("depth")
+        .and_then(|d| d.parse::<u32>().ok())
+        .unwrap_or(1)
+        .clamp(1, MAX_JSON_DEPTH)
+}
+
+/// Wrap the entries of the directory served at `url_path` into a listing.
+pub(crate) fn listing_json(url_path: &str, depth: u32, entries: Vec<Value>) -> String {
+    let listing = json!({
+        "path": url_path,
+        "depth": depth,
+        "entries": entries,
+    });
+
+    listing.to_string()
+}
+
+fn entries_json<'a>(
+    dir: &'a Path,
+    root: &'a SafeRoot,
+    options: &'a ResolverOptions,
+    sort: &'a ListingSort,
+    depth: u32,
+) -> Pin<Box<dyn Future<Output = io::Result<Vec<Value>>> + Send + 'a>> {
+    Box::pin(async move {
+        let mut entries = read_entries(dir, root, options).await?;
+        sort_entries(&mut entries, sort);
+
+        let mut out = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let mut value = entry_json(&entry);
+            if entry.is_dir && depth > 1 {
+                value["entries"] = Value::Array(entries_json(&entry.path, root, options, sort, depth - 1).await?);
+            }
+            out.push(value);
+        }
+
+        Ok(out)
+    })
+}
+
+/// Render a JSON listing for the directory served at `url_path`. The `depth`
+/// query parameter controls how many directory levels are included.
+pub(crate) async fn render_json(
+    url_path: &str,
+    dir: &Path,
+    root: &SafeRoot,
+    options: &ResolverOptions,
+    params: &HashMap<String, String>,
+) -> io::Result<String> {
+    let sort = ListingSort::from_query(params);
+    let depth = json_depth(params);
+    let entries = entries_json(dir, root, options, &sort, depth).await?;
+
+    Ok(listing_json(url_path, depth, entries))
+}
@@ -0,0 +1,212 @@
+use crate::{
+    archive_root::ArchiveResolver,
+    compression,
+    conditional,
+    encoding::{self, Encoding},
+    file_body::ResponseBody,
+    file_resolver::ResolverOptions,
+    mime_registry::MimeRegistry,
+    sniff,
+};
+use hyper::{body::Incoming, service::Service, Request, Response};
+use std::{
+    env,
+    fmt::Write as _,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// A file baked into the binary by [`include_assets!`](crate::include_assets),
+/// along with everything that can be worked out about it at build time.
+#[derive(Debug)]
+pub struct EmbeddedFile {
+    /// Path below the embedded directory, with `/` separators
+    pub path: &'static str,
+    pub data: &'static [u8],
+    /// Strong ETag of `data`, quoted
+    pub etag: &'static str,
+    /// `Content-Type`, including the charset of text
+    pub mime: &'static str,
+    /// Modification time in seconds since the Unix epoch
+    pub modified: Option<u64>,
+    pub br: Option<&'static [u8]>,
+    pub zstd: Option<&'static [u8]>,
+    pub gzip: Option<&'static [u8]>,
+}
+
+impl EmbeddedFile {
+    /// The precompressed variants, in server preference order.
+    pub(crate) fn variants(&self) -> Vec<(Encoding, &'static [u8])> {
+        [(Encoding::Brotli, self.br), (Encoding::Zstd, self.zstd), (Encoding::Gzip, self.gzip)]
+            .into_iter()
+            .filter_map(|(enc, data)| Some((enc, data?)))
+            .collect()
+    }
+}
+
+/// Settings for [`write_bundle`].
+#[derive(Clone, Debug)]
+pub struct BundleOptions {
+    /// Store brotli, zstd and gzip variants of compressible files, compressed
+    /// at the highest levels, where they come out smaller
+    pub precompress: bool,
+    /// Extension to `Content-Type` mapping; unknown files are sniffed
+    pub mime_types: MimeRegistry,
+}
+
+impl Default for BundleOptions {
+    fn default() -> Self {
+        BundleOptions {
+            precompress: true,
+            mime_types: MimeRegistry::default(),
+        }
+    }
+}
+
+/// Highest level of each codec, used for compressing ahead of time.
+fn max_level(encoding: Encoding) -> u32 {
+    match encoding {
+        Encoding::Brotli => 11,
+        Encoding::Zstd => 19,
+        Encoding::Gzip => 9,
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    println!("cargo:rerun-if-changed={}", dir.display());
+
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Embed every file below `dir`. Meant to be called from a build script:
+/// the table is written to `name` in `OUT_DIR`, for
+/// [`include_assets!`](crate::include_assets) to pick up, and Cargo is told
+/// to rerun the script when anything below `dir` changes.
+///
+/// ```ignore
+/// // build.rs
+/// qsrv::write_bundle("ui/dist", "ui.rs", &qsrv::BundleOptions::default()).unwrap();
+///
+/// // main.rs
+/// static UI: &[EmbeddedFile] = qsrv::include_assets!("ui.rs");
+/// let resolver = EmbeddedResolver::new(UI);
+/// ```
+pub fn write_bundle(dir: impl AsRef<Path>, name: &str, options: &BundleOptions) -> io::Result<()> {
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "OUT_DIR is not set; call write_bundle from a build script"))?);
+    let dir = fs::canonicalize(dir)?;
+    let variants_dir = out_dir.join(format!("{}.files", name));
+    if options.precompress {
+        fs::create_dir_all(&variants_dir)?;
+    }
+
+    let mut files = Vec::new();
+    collect_files(&dir, &mut files)?;
+
+    let mut out = String::from("// Generated by qsrv::write_bundle\n&[\n");
+    for (i, path) in files.iter().enumerate() {
+        let rel_path = path.strip_prefix(&dir)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let data = fs::read(path)?;
+        let modified = fs::metadata(path)?.modified().ok()
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+
+        let head = &data[..data.len().min(sniff::CHARSET_SNIFF_LEN)];
+        let mime = options.mime_types.content_type(path, head, true);
+
+        let mut variants = String::new();
+        for enc in encoding::PREFERENCE {
+            let field = match enc {
+                Encoding::Brotli => "br",
+                Encoding::Zstd => "zstd",
+                Encoding::Gzip => "gzip",
+            };
+            let worth_it = options.precompress
+                && compression::is_compressible(&mime)
+                && data.len() as u64 >= compression::DEFAULT_MIN_SIZE;
+            let encoded = if worth_it {
+                Some(compression::compress(&data, enc, Some(max_level(enc)))?)
+                    .filter(|encoded| encoded.len() < data.len())
+            } else {
+                None
+            };
+
+            match encoded {
+                Some(encoded) => {
+                    let variant_path = variants_dir.join(format!("{}.{}", i, enc.extension()));
+                    fs::write(&variant_path, encoded)?;
+                    writeln!(variants, "        {}: Some(include_bytes!({:?})),", field, variant_path).unwrap();
+                },
+                None => writeln!(variants, "        {}: None,", field).unwrap(),
+            }
+        }
+
+        writeln!(out, "    ::qsrv::responders::EmbeddedFile {{").unwrap();
+        writeln!(out, "        path: {:?},", rel_path).unwrap();
+        writeln!(out, "        data: include_bytes!({:?}),", path).unwrap();
+        writeln!(out, "        etag: {:?},", conditional::strong_etag_for(&data)).unwrap();
+        writeln!(out, "        mime: {:?},", mime).unwrap();
+        writeln!(out, "        modified: {:?},", modified).unwrap();
+        out.push_str(&variants);
+        writeln!(out, "    }},").unwrap();
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+    out.push_str("]\n");
+
+    fs::write(out_dir.join(name), out)
+}
+
+/// Expand to the `&'static [EmbeddedFile]` table that
+/// [`write_bundle`](crate::write_bundle) generated as `name` in `OUT_DIR`.
+#[macro_export]
+macro_rules! include_assets {
+    ($name:literal) => {
+        include!(concat!(env!("OUT_DIR"), "/", $name))
+    };
+}
+
+/// Serves files embedded with [`include_assets!`](crate::include_assets),
+/// with the same index, listing and redirect behaviour as a
+/// [`FileResolver`](crate::responders::FileResolver). ETags, types and
+/// precompressed variants come from the table; variants are sent whenever
+/// the client accepts them.
+#[derive(Clone)]
+pub struct EmbeddedResolver(pub(crate) ArchiveResolver);
+
+impl EmbeddedResolver {
+    pub fn new(files: &'static [EmbeddedFile]) -> Self {
+        EmbeddedResolver::with_options(files, ResolverOptions::default())
+    }
+
+    pub fn with_options(files: &'static [EmbeddedFile], options: ResolverOptions) -> Self {
+        EmbeddedResolver(ArchiveResolver::embedded(files, options))
+    }
+}
+
+impl Service<Request<Incoming>> for EmbeddedResolver {
+    type Response = Response<ResponseBody>;
+    type Error = hyper::Error;
+    type Future = <ArchiveResolver as Service<Request<Incoming>>>::Future;
+
+    fn call(&mut self, req: Request<Incoming>) -> Self::Future {
+        self.0.call(req)
+    }
+}
@@ -0,0 +1,96 @@
+use crate::safe_open::SafeRoot;
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+/// Content codings qsrv knows how to serve.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+/// Server preference when the client gives several codings the same weight.
+pub(crate) const PREFERENCE: [Encoding; 3] = [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip];
+
+impl Encoding {
+    /// Token used in `Accept-Encoding` and `Content-Encoding`.
+    pub fn token(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// File extension of a precompressed sidecar file.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zst",
+            Encoding::Gzip => "gz",
+        }
+    }
+}
+
+/// Parse an `Accept-Encoding` header into the acceptable codings, best first.
+/// Codings with `q=0` are dropped, `*` stands in for any coding not listed and
+/// ties are broken by server preference.
+pub(crate) fn accepted_encodings(header: Option<&str>) -> Vec<Encoding> {
+    let header = match header {
+        Some(h) => h,
+        None => return Vec::new(),
+    };
+
+    let mut listed: Vec<(String, f32)> = Vec::new();
+    for item in header.split(',') {
+        let mut params = item.split(';').map(str::trim);
+        let coding = params.next().unwrap_or("").to_ascii_lowercase();
+        if coding.is_empty() {
+            continue;
+        }
+        let q = params
+            .filter_map(|p| p.strip_prefix("q=").or_else(|| p.strip_prefix("Q=")))
+            .filter_map(|q| q.trim().parse::<f32>().ok())
+            .next()
+            .unwrap_or(1.0);
+        listed.push((coding, q));
+    }
+
+    let wildcard = listed.iter().find(|(c, _)| c == "*").map(|(_, q)| *q);
+    let mut accepted: Vec<(Encoding, f32)> = PREFERENCE.iter()
+        .filter_map(|enc| {
+            let q = listed.iter()
+                .find(|(c, _)| c == enc.token() || (*enc == Encoding::Gzip && c == "x-gzip"))
+                .map(|(_, q)| *q)
+                .or(wildcard)?;
+            if q > 0.0 { Some((*enc, q)) } else { None }
+        })
+        .collect();
+
+    // Stable sort keeps server preference for equal weights
+    accepted.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    accepted.into_iter().map(|(enc, _)| enc).collect()
+}
+
+/// Find and open the best precompressed sibling of `path` (e.g. `app.js.br`)
+/// that the client accepts. Sidecars are opened through `root`, so they obey
+/// the same containment rules as the file itself.
+pub(crate) async fn find_precompressed(path: &Path, root: &SafeRoot, accepted: &[Encoding]) -> Option<(File, PathBuf, Encoding)> {
+    for enc in accepted {
+        let mut sidecar = path.as_os_str().to_os_string();
+        sidecar.push(".");
+        sidecar.push(enc.extension());
+
+        if let Ok((file, sidecar_path)) = root.open(Path::new(&sidecar)).await {
+            if file.metadata().map(|m| m.is_file()).unwrap_or(false) {
+                return Some((file, sidecar_path, *enc));
+            }
+        }
+    }
+
+    None
+}
@@ -0,0 +1,97 @@
+use crate::{
+    file_body::{full_body, ResponseBody},
+    file_resolver::ResolverOptions,
+    safe_open::SafeRoot,
+    util,
+};
+use hyper::{Response, StatusCode};
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
+use tokio::{fs, io::AsyncReadExt};
+use tracing::{error, trace};
+
+/// Error documents looked up in the document root unless configured otherwise.
+pub(crate) fn default_error_pages() -> HashMap<u16, String> {
+    [404u16, 403, 500].iter()
+        .map(|status| (*status, format!("{}.html", status)))
+        .collect()
+}
+
+/// Built-in page used when the document root has no error document.
+pub(crate) fn builtin_page(status: u16, reason: &str) -> String {
+    format!("<!DOCTYPE html>\n\
+        <html>\n\
+        <head>\n\
+        <meta charset=\"utf-8\">\n\
+        <title>{status} {reason}</title>\n\
+        <style>\n\
+        body {{ font-family: sans-serif; color: #333; text-align: center; margin-top: 15vh; }}\n\
+        h1 {{ font-size: 4em; margin: 0; color: #999; }}\n\
+        p {{ font-size: 1.3em; }}\n\
+        </style>\n\
+        </head>\n\
+        <body>\n\
+        <h1>{status}</h1>\n\
+        <p>{reason}</p>\n\
+        </body>\n\
+        </html>\n")
+}
+
+/// Where a resolver reads its error documents from.
+pub(crate) trait PageSource {
+    /// Read the error document `page`, named relative to the document root.
+    /// Returns its contents and the path its type is worked out from.
+    async fn read_page(&self, page: &str) -> io::Result<(Vec<u8>, PathBuf)>;
+}
+
+impl PageSource for SafeRoot {
+    /// Read the page through the root, so it is held to the same containment
+    /// and symlink rules as any other file.
+    async fn read_page(&self, page: &str) -> io::Result<(Vec<u8>, PathBuf)> {
+        let page = util::normalize_request_path(&format!("/{}", page.trim_start_matches('/')), false)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidInput, format!("invalid path: {:?}", e)))?;
+        let (file, path) = self.open(&self.path().join(&page[1..])).await?;
+
+        let mut file = fs::File::from(file);
+        if !file.metadata().await?.is_file() {
+            return Err(io::Error::new(ErrorKind::NotFound, "not a file"));
+        }
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf).await?;
+
+        Ok((buf, path))
+    }
+}
+
+/// Build an error response for `status`, using the configured error document
+/// from `pages` if there is one and falling back to the built-in page.
+pub(crate) async fn error_response(status: StatusCode, pages: &impl PageSource, options: &ResolverOptions) -> Response<ResponseBody> {
+    if let Some(page) = options.error_pages.get(&status.as_u16()) {
+        match pages.read_page(page).await {
+            Ok((buf, path)) => {
+                return Response::builder()
+                    .status(status)
+                    .header("Content-Type", options.mime_types.content_type(&path, &buf, options.sniff))
+                    .body(full_body(buf))
+                    .unwrap();
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => trace!("no error document for {}", status.as_u16()),
+            Err(e) => error!("Failed to read error document {}: {}", page, e),
+        }
+    }
+
+    builtin_response(status)
+}
+
+/// Build an error response for `status` with the built-in page.
+pub(crate) fn builtin_response(status: StatusCode) -> Response<ResponseBody> {
+    let reason = status.canonical_reason().unwrap_or("Error");
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body(full_body(builtin_page(status.as_u16(), reason)))
+        .unwrap()
+}
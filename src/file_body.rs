@@ -0,0 +1,144 @@
+use http_body_util::{combinators::BoxBody, BodyExt, Full};
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use std::{
+    collections::VecDeque,
+    io::{self, SeekFrom},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncSeek, ReadBuf},
+};
+
+/// Largest chunk read from disk per frame.
+pub(crate) const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Body type produced by the resolvers.
+pub type ResponseBody = BoxBody<Bytes, io::Error>;
+
+/// Wrap an in-memory buffer as a [`ResponseBody`].
+pub(crate) fn full_body(data: impl Into<Bytes>) -> ResponseBody {
+    Full::new(data.into())
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+/// Build a body from `parts` of a file whose contents are already in memory.
+pub(crate) fn memory_body(data: &Bytes, parts: Vec<Part>) -> ResponseBody {
+    let mut chunks: Vec<Bytes> = parts.into_iter()
+        .map(|part| match part {
+            Part::Bytes(b) => b,
+            Part::File { start, len } => data.slice(start as usize..(start + len) as usize),
+        })
+        .collect();
+
+    match chunks.len() {
+        1 => full_body(chunks.pop().unwrap()),
+        _ => full_body(chunks.concat()),
+    }
+}
+
+/// A piece of a [`FileBody`]: either literal bytes or a span of the file.
+pub(crate) enum Part {
+    Bytes(Bytes),
+    File { start: u64, len: u64 },
+}
+
+impl Part {
+    pub(crate) fn len(&self) -> u64 {
+        match self {
+            Part::Bytes(b) => b.len() as u64,
+            Part::File { len, .. } => *len,
+        }
+    }
+}
+
+enum State {
+    Idle,
+    Seeking { remaining: u64 },
+    Reading { remaining: u64 },
+}
+
+/// Streams parts of a file from disk in chunks. Frames are only read when
+/// hyper polls for them, so slow clients apply backpressure to disk reads.
+pub(crate) struct FileBody {
+    file: File,
+    parts: VecDeque<Part>,
+    state: State,
+    buf: Vec<u8>,
+    remaining: u64,
+}
+
+impl FileBody {
+    pub fn new(file: File, parts: Vec<Part>) -> Self {
+        let remaining = parts.iter().map(Part::len).sum();
+
+        FileBody {
+            file,
+            parts: parts.into(),
+            state: State::Idle,
+            buf: Vec::new(),
+            remaining,
+        }
+    }
+}
+
+impl Body for FileBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+
+        loop {
+            match this.state {
+                State::Idle => match this.parts.pop_front() {
+                    None => return Poll::Ready(None),
+                    Some(Part::Bytes(b)) => {
+                        this.remaining -= b.len() as u64;
+                        return Poll::Ready(Some(Ok(Frame::data(b))));
+                    },
+                    Some(Part::File { start, len }) => {
+                        Pin::new(&mut this.file).start_seek(SeekFrom::Start(start))?;
+                        this.state = State::Seeking { remaining: len };
+                    },
+                },
+                State::Seeking { remaining } => {
+                    ready!(Pin::new(&mut this.file).poll_complete(cx))?;
+                    this.state = State::Reading { remaining };
+                },
+                State::Reading { remaining: 0 } => this.state = State::Idle,
+                State::Reading { remaining } => {
+                    let want = remaining.min(CHUNK_SIZE as u64) as usize;
+                    this.buf.resize(want, 0);
+                    let mut read_buf = ReadBuf::new(&mut this.buf);
+                    ready!(Pin::new(&mut this.file).poll_read(cx, &mut read_buf))?;
+
+                    let filled = read_buf.filled().len();
+                    if filled == 0 {
+                        return Poll::Ready(Some(Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "file shrank while being served"))));
+                    }
+
+                    let chunk = Bytes::copy_from_slice(&this.buf[..filled]);
+                    this.state = State::Reading { remaining: remaining - filled as u64 };
+                    this.remaining -= filled as u64;
+                    return Poll::Ready(Some(Ok(Frame::data(chunk))));
+                },
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.remaining == 0
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining)
+    }
+}
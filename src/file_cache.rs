@@ -0,0 +1,246 @@
+use crate::conditional;
+use hyper::body::Bytes;
+use lru::LruCache;
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::Metadata,
+    io,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::SystemTime,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+    sync::OnceCell,
+};
+use tracing::trace;
+
+/// Identifies a version of a file. Cached contents are only reused while the
+/// file on disk still has the same stamp.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+struct Stamp {
+    len: u64,
+    modified: Option<SystemTime>,
+    dev: u64,
+    ino: u64,
+}
+
+impl Stamp {
+    #[cfg(unix)]
+    fn of(meta: &Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+
+        Stamp {
+            len: meta.len(),
+            modified: meta.modified().ok(),
+            dev: meta.dev(),
+            ino: meta.ino(),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn of(meta: &Metadata) -> Self {
+        Stamp {
+            len: meta.len(),
+            modified: meta.modified().ok(),
+            dev: 0,
+            ino: 0,
+        }
+    }
+}
+
+/// Contents of a file held in memory.
+pub(crate) struct CachedFile {
+    stamp: Stamp,
+    pub data: Bytes,
+    strong_etag: OnceLock<String>,
+}
+
+impl CachedFile {
+    /// Same as [`conditional::strong_etag`], computed once per version.
+    pub fn strong_etag(&self) -> &str {
+        self.strong_etag.get_or_init(|| conditional::strong_etag_for(&self.data))
+    }
+}
+
+/// Whether a response was served from the cache, stored in the response
+/// extensions for the request log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CacheStatus {
+    Hit,
+    Miss,
+}
+
+/// A read shared by every request for the same file version that arrives
+/// while it is in progress. `None` if the read failed.
+type PendingRead = Arc<OnceCell<Option<Arc<CachedFile>>>>;
+
+struct State {
+    entries: LruCache<PathBuf, Arc<CachedFile>>,
+    bytes: u64,
+    pending: HashMap<(PathBuf, Stamp), PendingRead>,
+}
+
+/// A bounded LRU cache of file contents, keyed by resolved path. Entries are
+/// checked against the size, modification time and inode of the file that
+/// was just opened, so changed files are never served stale. Concurrent
+/// requests for the same uncached file share a single read.
+#[derive(Clone)]
+pub struct FileCache {
+    state: Arc<Mutex<State>>,
+    max_bytes: u64,
+    max_file_size: u64,
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+}
+
+impl FileCache {
+    /// Default number of files kept in memory.
+    pub const DEFAULT_MAX_ENTRIES: usize = 1024;
+
+    /// Default total size of the cached contents.
+    pub const DEFAULT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+    /// Default size limit for a single cached file.
+    pub const DEFAULT_MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+    /// A cache holding at most `max_entries` files and `max_bytes` of
+    /// contents. Files larger than `max_file_size` are always read from disk.
+    pub fn new(max_entries: usize, max_bytes: u64, max_file_size: u64) -> Self {
+        let capacity = NonZeroUsize::new(max_entries).unwrap_or(NonZeroUsize::MIN);
+
+        FileCache {
+            state: Arc::new(Mutex::new(State {
+                entries: LruCache::new(capacity),
+                bytes: 0,
+                pending: HashMap::new(),
+            })),
+            max_bytes,
+            max_file_size,
+            hits: Arc::new(AtomicU64::new(0)),
+            misses: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Number of requests answered from memory, including those that waited
+    /// for another request's read.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of requests that read a file into the cache.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Contents of `file`, opened at `path` and described by `meta`, from
+    /// memory or read now. `None` if the file is not cacheable, in which
+    /// case it should be streamed from disk.
+    pub(crate) async fn get(&self, path: &Path, file: &mut File, meta: &Metadata) -> io::Result<Option<(Arc<CachedFile>, CacheStatus)>> {
+        if meta.len() > self.max_file_size || meta.len() > self.max_bytes {
+            return Ok(None);
+        }
+
+        let stamp = Stamp::of(meta);
+        let key = (path.to_path_buf(), stamp);
+        let pending = {
+            let mut state = self.state.lock().unwrap();
+            if let Some(entry) = state.entries.get(path) {
+                if entry.stamp == stamp {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(Some((Arc::clone(entry), CacheStatus::Hit)));
+                }
+            }
+            Arc::clone(state.pending.entry(key.clone()).or_default())
+        };
+
+        let mut read_here = false;
+        let mut read_error = None;
+        let entry = pending.get_or_init(|| async {
+            read_here = true;
+            match read_contents(file, stamp).await {
+                Ok(entry) => entry,
+                Err(e) => {
+                    read_error = Some(e);
+                    None
+                },
+            }
+        }).await.clone();
+
+        if !read_here {
+            return Ok(entry.map(|entry| {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                (entry, CacheStatus::Hit)
+            }));
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let mut state = self.state.lock().unwrap();
+        state.pending.remove(&key);
+        if let Some(e) = read_error {
+            return Err(e);
+        }
+        if let Some(entry) = &entry {
+            self.insert(&mut state, path, Arc::clone(entry));
+        }
+
+        Ok(entry.map(|entry| (entry, CacheStatus::Miss)))
+    }
+
+    fn insert(&self, state: &mut State, path: &Path, entry: Arc<CachedFile>) {
+        state.bytes += entry.data.len() as u64;
+        if let Some((_, old)) = state.entries.push(path.to_path_buf(), entry) {
+            state.bytes -= old.data.len() as u64;
+        }
+        while state.bytes > self.max_bytes {
+            match state.entries.pop_lru() {
+                Some((evicted, old)) => {
+                    trace!("evicting {:?} from the file cache", evicted.as_os_str());
+                    state.bytes -= old.data.len() as u64;
+                },
+                None => break,
+            }
+        }
+    }
+}
+
+impl fmt::Debug for FileCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileCache")
+            .field("max_bytes", &self.max_bytes)
+            .field("max_file_size", &self.max_file_size)
+            .field("hits", &self.hits())
+            .field("misses", &self.misses())
+            .finish()
+    }
+}
+
+impl Default for FileCache {
+    fn default() -> Self {
+        FileCache::new(FileCache::DEFAULT_MAX_ENTRIES, FileCache::DEFAULT_MAX_BYTES, FileCache::DEFAULT_MAX_FILE_SIZE)
+    }
+}
+
+/// Read the whole file, leaving it rewound for callers that go on to read
+/// it themselves. `None` if it changed size while being read.
+async fn read_contents(file: &mut File, stamp: Stamp) -> io::Result<Option<Arc<CachedFile>>> {
+    file.rewind().await?;
+    let mut data = Vec::with_capacity(stamp.len as usize);
+    (&mut *file).take(stamp.len + 1).read_to_end(&mut data).await?;
+    file.rewind().await?;
+    if data.len() as u64 != stamp.len {
+        return Ok(None);
+    }
+
+    Ok(Some(Arc::new(CachedFile {
+        stamp,
+        data: data.into(),
+        strong_etag: OnceLock::new(),
+    })))
+}
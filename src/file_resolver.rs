@@ -1,196 +1,746 @@
 // use crate::{HttpResponse, HttpRequest, Responder};
-use http_body_util::Full;
+use crate::{
+    access_policy::{self, AccessPolicy},
+    cache_control::CachePolicy,
+    compression::{self, CompressedBody},
+    conditional::{self, Precondition, Validators},
+    directory_listing,
+    encoding,
+    error_pages::{self, error_response, PageSource},
+    file_body::{full_body, memory_body, FileBody, Part, ResponseBody},
+    file_cache::{CacheStatus, FileCache},
+    markdown,
+    mime_registry::MimeRegistry,
+    mount_table,
+    range::{self, RangeRequest},
+    safe_open::{SafeRoot, SymlinkPolicy},
+    sniff,
+    ssi::{self, ServerIncludes},
+    util,
+    zero_copy::{self, SendfileBody, SendfileQueue},
+};
+use http_body_util::BodyExt;
 use hyper::{
     service::Service,
-    body::{Incoming, Bytes},
-    Request, Response, StatusCode,
+    body::{Body, Incoming, Bytes},
+    header::HeaderValue,
+    http::{request::Parts, response},
+    Method, Request, Response, StatusCode,
 };
 use std::{
+    collections::HashMap,
     error::Error,
     future::Future,
-    io::ErrorKind,
+    io::{self, ErrorKind},
     path::{Path, PathBuf},
     pin::Pin,
+    sync::Arc,
 };
-use tokio::fs;
+use tokio::{fs, io::AsyncReadExt};
 use tracing::{error, info, trace};
 
-fn mime_for_file_ext(path: &Path) -> String {
-    let mime = match path.extension() {
-        Some(e) => match e.to_str() {
-            Some(s) => match s {
-                "aac" => "audio/aac",
-                "abw" => "application/x-abiword",
-                "arc" => "application/x-freearc",
-                "avif" => "image/avif",
-                "avi" => "video/x-msvideo",
-                "azw" => "application/vnd.amazon.ebook",
-                "bmp" => "image/bmp",
-                "bz" => "application/x-bzip",
-                "bz2" => "application/x-bzip2",
-                "cda" => "application/x-cdf",
-                "csh" => "application/x-csh",
-                "css" => "text/css",
-                "csv" => "text/csv",
-                "doc" => "application/msword",
-                "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
-                "eot" => "application/vnd.ms-fontobject",
-                "epub" => "application/epub+zip",
-                "gz" => "application/gzip",
-                "gif" => "image/gif",
-                "htm" | "html" => "text/html",
-                "ico" => "image/vnd.microsoft.icon",
-                "ics" => "text/calendar",
-                "jar" => "application/java-archive",
-                "jpg" | "jpeg" => "image/jpeg",
-                "js" | "mjs" => "text/javascript",
-                "json" => "application/json",
-                "jsonld" => "application/ld+json",
-                "mid" | "midi" => "audio/midi",
-                "mp3" => "audio/mpeg",
-                "mp4" => "video/mp4",
-                "mpg" |"mpeg" => "video/mpeg",
-                "mpkg" => "application/vnd.apple.installer+xml",
-                "odp" => "application/vnd.oasis.opendocument.presentation",
-                "ods" => "application/vnd.oasis.opendocument.spreadsheet",
-                "odt" => "application/vnd.oasis.opendocument.text",
-                "oga" | "ogg" => "audio/ogg",
-                "ogv" => "video/ogg",
-                "ogx" => "application/ogg",
-                "opus" => "audio/opus",
-                "otf" => "font/otf",
-                "png" => "image/png",
-                "pdf" => "application/pdf",
-                "php" => "application/x-httpd-php",
-                "ppt" => "application/vnd.ms-powerpoint",
-                "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
-                "rar" => "application/vnd.rar",
-                "rtf" => "application/rtf",
-                "sh" => "application/x-sh",
-                "svg" => "image/svg+xml",
-                "tar" => "application/x-tar",
-                "tif" | "tiff" => "image/tiff",
-                "ts" => "video/mp2t",
-                "ttf" => "font/ttf",
-                "txt" => "text/plain",
-                "vsd" => "application/vnd.visio",
-                "wasm" => "application/wasm",
-                "wav" => "audio/wav",
-                "weba" => "audio/webm",
-                "webm" => "video/webm",
-                "webp" => "image/webp",
-                "woff" => "font/woff",
-                "woff2" => "font/woff2",
-                "xhtml" => "application/xhtml+xml",
-                "xls" => "application/vnd.ms-excel",
-                "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
-                "xml" => "application/xml",
-                "xul" => "application/vnd.mozilla.xul+xml",
-                "zip" => "application/zip",
-                "7z" => "application/x-7z-compressed",
-                _ => "application/octet-stream",
-            },
-            None => "application/octet-stream",
-        },
-        None => "application/octet-stream",
-    };
-    if mime.starts_with("text/") {
-        format!("{}; charset=utf-8", mime)
-    } else {
-        String::from(mime)
+/// Behaviour switches for a [`FileResolver`].
+#[derive(Clone, Debug)]
+pub struct ResolverOptions {
+    /// Render a directory listing when a directory has no index.html
+    pub autoindex: bool,
+    /// Derive ETags from a hash of the file contents instead of its size and
+    /// modification time
+    pub strong_etags: bool,
+    /// Serve precompressed `.br`, `.zst` and `.gz` siblings when the client
+    /// accepts the encoding
+    pub precompressed: bool,
+    /// Compress compressible responses on the fly
+    pub compress: bool,
+    /// Codec-specific compression level; `None` picks a moderate default
+    pub compression_level: Option<u32>,
+    /// Smallest file size, in bytes, that is compressed on the fly
+    pub compression_min_size: u64,
+    /// Document, relative to the root, served for unknown navigation paths
+    /// of single-page applications
+    pub spa_fallback: Option<String>,
+    /// Error documents, relative to the root, keyed by status code
+    pub error_pages: HashMap<u16, String>,
+    /// Treat `%2F` in request paths as a separator instead of rejecting it
+    pub allow_encoded_slashes: bool,
+    /// File names tried, in order, when a directory is requested
+    pub index_files: Vec<String>,
+    /// Serve `/about` from `about.html` when `/about` does not exist
+    pub clean_urls: bool,
+    /// Redirect directories to a trailing slash and files away from one
+    pub slash_redirects: bool,
+    /// Which files may be served at all
+    pub access: AccessPolicy,
+    /// Which symlinks are followed when opening files
+    pub symlinks: SymlinkPolicy,
+    /// Rules for the `Cache-Control` header
+    pub cache: CachePolicy,
+    /// Extension to `Content-Type` mapping
+    pub mime_types: MimeRegistry,
+    /// In-memory cache for small files, shared by every resolver it is given to
+    pub file_cache: Option<FileCache>,
+    /// Sniff the type of files whose extension is missing or unknown instead
+    /// of sending them as `application/octet-stream` with `nosniff`
+    pub sniff: bool,
+    /// Smallest file size, in bytes, sent with `sendfile(2)` on connections
+    /// served by [`serve_zero_copy`](crate::serve_zero_copy); `None` always streams
+    pub sendfile_min_size: Option<u64>,
+    /// Render Markdown documents to HTML for browsers, and use README.md as
+    /// the index of directories without one
+    pub markdown: bool,
+    /// Page rendered Markdown is put into instead of
+    /// [`DEFAULT_MARKDOWN_TEMPLATE`](crate::responders::DEFAULT_MARKDOWN_TEMPLATE)
+    pub markdown_template: Option<String>,
+    /// Documents assembled from server-side include directives
+    pub includes: Option<ServerIncludes>,
+}
+
+impl Default for ResolverOptions {
+    fn default() -> Self {
+        ResolverOptions {
+            autoindex: false,
+            strong_etags: false,
+            precompressed: false,
+            compress: false,
+            compression_level: None,
+            compression_min_size: compression::DEFAULT_MIN_SIZE,
+            spa_fallback: None,
+            error_pages: error_pages::default_error_pages(),
+            allow_encoded_slashes: false,
+            index_files: vec!["index.html".to_string()],
+            clean_urls: false,
+            slash_redirects: true,
+            access: AccessPolicy::default(),
+            symlinks: SymlinkPolicy::default(),
+            cache: CachePolicy::default(),
+            mime_types: MimeRegistry::default(),
+            file_cache: None,
+            sniff: false,
+            sendfile_min_size: Some(zero_copy::DEFAULT_MIN_SIZE),
+            markdown: false,
+            markdown_template: None,
+            includes: None,
+        }
     }
 }
 
+#[derive(Clone)]
 pub struct FileResolver {
-    root_path: PathBuf,
+    root: SafeRoot,
+    options: Arc<ResolverOptions>,
+    /// URL prefix the resolver is mounted at, without a trailing slash
+    pub(crate) prefix: String,
 }
 
 impl FileResolver {
     pub fn new(root: &str) -> Result<Self, Box<dyn Error>> {
+        FileResolver::with_options(root, ResolverOptions::default())
+    }
+
+    pub fn with_options(root: &str, options: ResolverOptions) -> Result<Self, Box<dyn Error>> {
         let can_path = std::fs::canonicalize(Path::new(root))?;
         trace!("root canonical path: {:?}", can_path.as_os_str());
 
         Ok(FileResolver {
-            root_path: can_path,
+            root: SafeRoot::new(can_path, options.symlinks)?,
+            options: Arc::new(options),
+            prefix: String::new(),
         })
     }
 }
 
-impl Service<Request<Incoming>> for FileResolver {
-    type Response = Response<Full<Bytes>>;
-    type Error = hyper::Error;
-    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+/// Whether `path` is a directory reachable through `root` under its symlink
+/// policy.
+async fn is_dir(root: &SafeRoot, path: &Path) -> bool {
+    root.metadata(path).await.map(|m| m.is_dir()).unwrap_or(false)
+}
 
-    fn call(&mut self, req: Request<Incoming>) -> Self::Future {
-        let root = self.root_path.clone();
+/// Whether `path` is a file reachable through `root` under its symlink policy.
+async fn is_file(root: &SafeRoot, path: &Path) -> bool {
+    root.metadata(path).await.map(|m| m.is_file()).unwrap_or(false)
+}
+
+fn with_html_ext(path: &Path) -> PathBuf {
+    let mut html_path = path.as_os_str().to_os_string();
+    html_path.push(".html");
 
-        let mut working_path = PathBuf::from(&root);
-        working_path.push(&req.uri().path()[1..]);
-        if working_path.is_dir() {
-            trace!("requested directory - serving index");
-            working_path.push("index.html");
+    PathBuf::from(html_path)
+}
+
+/// Return the first of `index_files` that exists as a file in `dir`.
+async fn find_index(root: &SafeRoot, dir: &Path, index_files: &[String]) -> Option<PathBuf> {
+    for name in index_files {
+        let index_path = dir.join(name);
+        if is_file(root, &index_path).await {
+            return Some(index_path);
         }
-        trace!("working request path: {:?}", working_path.as_os_str());
+    }
 
-        Box::pin(async move {
-            let working_path = match fs::canonicalize(working_path).await {
-                Ok(p) => p,
-                Err(e) => {
-                    match e.kind() {
-                        ErrorKind::NotFound => trace!("Failed to canonicalize path: Not found"),
-                        _ => error!("Failed to canonicalize path: {}", e),
-                    }
-                    let res = Response::builder() .status(StatusCode::NOT_FOUND) .body(Full::new(Bytes::from("Not found".to_string()))).unwrap();
-
-                info!("{} {} \"{}\" {}",
-                      res.status(),
-                      req.method(),
-                      req.uri().path_and_query().unwrap(),
-                      "-");
-
-                    return Ok(res);
-                },
-            };
+    None
+}
 
-            if !working_path.starts_with(&root) {
-                let res = Response::builder().status(StatusCode::FORBIDDEN).body(Full::new(Bytes::from("Forbidden".to_string()))).unwrap();
+/// Permanently redirect to `path` (decoded), keeping the query string. Safe
+/// methods get a 301; everything else a 308 so the method is preserved.
+pub(crate) fn redirect(req: &Parts, path: &str) -> Response<ResponseBody> {
+    let status = if req.method == Method::GET || req.method == Method::HEAD {
+        StatusCode::MOVED_PERMANENTLY
+    } else {
+        StatusCode::PERMANENT_REDIRECT
+    };
+
+    let mut location = util::percent_encode_path(path);
+    if let Some(query) = req.uri.query() {
+        location.push('?');
+        location.push_str(query);
+    }
+    trace!("redirecting to {}", location);
+
+    Response::builder()
+        .status(status)
+        .header("Location", location)
+        .body(full_body(Bytes::new()))
+        .unwrap()
+}
 
-                info!("{} {} \"{}\" {}",
-                      res.status(),
-                      req.method(),
-                      req.uri().path_and_query().unwrap(),
-                      "-");
+/// Resolve `path`, relative to the mount point at `prefix`.
+async fn resolve(root: &SafeRoot, options: &ResolverOptions, req: &Parts, prefix: &str, path: &str) -> Response<ResponseBody> {
+    if !options.access.permits(path) {
+        trace!("access policy refused {}", path);
+        return error_response(StatusCode::NOT_FOUND, root, options).await;
+    }
+
+    let mut working_path = root.path().to_path_buf();
+    working_path.push(&path[1..]);
+    let params = util::parse_query(req.uri.query().unwrap_or(""));
+    let accept = req.headers.get("Accept").and_then(|v| v.to_str().ok());
+    let want_json = directory_listing::wants_json(accept, &params);
+    let url_path = format!("{}{}", prefix, path);
+
+    let mut list_dir = false;
+    if is_dir(root, &working_path).await {
+        if !path.ends_with('/') && options.slash_redirects {
+            return redirect(req, &format!("{}{}/", prefix, path));
+        }
+
+        let mut index = find_index(root, &working_path, &options.index_files).await;
+        if index.is_none() && options.markdown {
+            index = find_index(root, &working_path, &[markdown::README.to_string()]).await;
+        }
 
-                return Ok(res);
+        match index {
+            Some(index_path) if !(options.autoindex && want_json) => {
+                trace!("requested directory - serving index");
+                working_path = index_path;
+            },
+            _ if options.autoindex => {
+                trace!("requested directory without index - serving listing");
+                list_dir = true;
+            },
+            _ => working_path.push(options.index_files.first().map_or("index.html", String::as_str)),
+        }
+    } else if !is_file(root, &working_path).await {
+        let trimmed = path.trim_end_matches('/');
+        let trimmed_path = root.path().join(&trimmed[1..]);
+        if path.len() > 1 && path.ends_with('/') && options.slash_redirects
+            && (is_file(root, &trimmed_path).await
+                || (options.clean_urls && is_file(root, &with_html_ext(&trimmed_path)).await))
+        {
+            return redirect(req, &format!("{}{}", prefix, trimmed));
+        }
+
+        if options.clean_urls && !path.ends_with('/') {
+            let html_path = with_html_ext(&working_path);
+            if is_file(root, &html_path).await {
+                trace!("clean URL - serving {:?}", html_path.as_os_str());
+                working_path = html_path;
+            }
+        }
+    }
+    trace!("working request path: {:?}", working_path.as_os_str());
+
+    let (file, resolved_path) = match root.open(&working_path).await {
+        Ok(opened) => opened,
+        Err(e) => {
+            match e.kind() {
+                ErrorKind::NotFound => trace!("Failed to open path: Not found"),
+                ErrorKind::PermissionDenied => trace!("Refused to open path: {}", e),
+                _ => error!("Failed to open path: {}", e),
+            }
+            if e.kind() == ErrorKind::PermissionDenied {
+                return error_response(StatusCode::FORBIDDEN, root, options).await;
+            }
+            if let Some(fallback) = &options.spa_fallback {
+                if e.kind() == ErrorKind::NotFound && is_navigation(req, path) {
+                    return serve_spa_fallback(fallback, root, options, req).await;
+                }
             }
+            return error_response(StatusCode::NOT_FOUND, root, options).await;
+        },
+    };
+
+    if !options.access.permits_path(&resolved_path, root.path()) {
+        trace!("access policy refused {:?}", resolved_path.as_os_str());
+        return error_response(StatusCode::NOT_FOUND, root, options).await;
+    }
+
+    if list_dir && want_json {
+        return match directory_listing::render_json(&url_path, &working_path, root, options, &params).await {
+            Ok(json) => listing_response(options, path, "application/json", json),
+            Err(e) => {
+                error!("Failed to read directory: {}", e);
+                error_response(StatusCode::INTERNAL_SERVER_ERROR, root, options).await
+            },
+        };
+    }
 
-            if let Ok(buf) = fs::read(&working_path).await {
-                let buf_size = buf.len();
-                let mime = mime_for_file_ext(&working_path);
+    if list_dir {
+        let sort = directory_listing::ListingSort::from_query(&params);
 
-                let res = Response::builder().status(200)
-                    .header("Content-Type", mime)
-                    .body(Full::new(Bytes::from(buf)))
-                    .unwrap();
+        return match directory_listing::read_entries(&working_path, root, options).await {
+            Ok(mut entries) => {
+                directory_listing::sort_entries(&mut entries, &sort);
+                let html = directory_listing::render_html(&url_path, &entries, &sort);
 
-                info!("{} {} \"{}\" {}",
-                      res.status(),
-                      req.method(),
-                      req.uri().path_and_query().unwrap(),
-                      buf_size);
+                listing_response(options, path, "text/html; charset=utf-8", html)
+            },
+            Err(e) => {
+                error!("Failed to read directory: {}", e);
+                error_response(StatusCode::INTERNAL_SERVER_ERROR, root, options).await
+            },
+        };
+    }
 
-                return Ok(res);
+    let mut file = fs::File::from(file);
+    if options.markdown && markdown::is_markdown(&resolved_path) && markdown::wants_rendered(req) {
+        match serve_markdown(&mut file, &resolved_path, root, options, req).await {
+            Ok(Some(res)) => return res,
+            Ok(None) => (),
+            Err(e) => {
+                trace!("Failed to render Markdown: {}", e);
+                return error_response(StatusCode::NOT_FOUND, root, options).await;
+            },
+        }
+    }
+
+    if options.includes.as_ref().is_some_and(|includes| includes.applies_to(&resolved_path, root.path())) {
+        match ssi::processed_response(&mut file, &resolved_path, path, root, options, req).await {
+            Ok(Some(res)) => return res,
+            Ok(None) => trace!("too large for includes - serving as is"),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                trace!("Failed to process includes: Not found");
+                return error_response(StatusCode::NOT_FOUND, root, options).await;
+            },
+            Err(e) => {
+                error!("Failed to process includes: {}", e);
+                return error_response(StatusCode::INTERNAL_SERVER_ERROR, root, options).await;
+            },
+        }
+    }
+
+    match serve_file(file, &resolved_path, root, options, req).await {
+        Ok(res) => res,
+        Err(e) => {
+            match e.kind() {
+                ErrorKind::NotFound => trace!("Failed to open file: Not found"),
+                _ => error!("Failed to open file: {}", e),
             }
+            error_response(StatusCode::NOT_FOUND, root, options).await
+        },
+    }
+}
+
+pub(crate) fn listing_response(options: &ResolverOptions, path: &str, mime: &str, body: String) -> Response<ResponseBody> {
+    let mut builder = Response::builder().status(200)
+        .header("Content-Type", mime);
+    if let Some(directives) = options.cache.directives(path, mime) {
+        builder = builder.header("Cache-Control", directives);
+    }
+
+    builder.body(full_body(body)).unwrap()
+}
+
+/// Whether the client lists `text/html` in its `Accept` header.
+pub(crate) fn accepts_html(req: &Parts) -> bool {
+    req.headers.get("Accept")
+        .and_then(|v| v.to_str().ok())
+        .map(|accept| accept.split(',').any(|range| {
+            let media = range.split(';').next().unwrap_or("").trim();
+            media.eq_ignore_ascii_case("text/html")
+        }))
+        .unwrap_or(false)
+}
+
+/// Whether a request looks like a browser navigation to a client-side route
+/// rather than a request for a missing asset: a GET or HEAD for a path whose
+/// last segment has no extension, from a client that accepts HTML.
+pub(crate) fn is_navigation(req: &Parts, path: &str) -> bool {
+    if req.method != Method::GET && req.method != Method::HEAD {
+        return false;
+    }
+
+    let last_segment = path.rsplit('/').next().unwrap_or("");
+    if last_segment.contains('.') {
+        return false;
+    }
 
-            let res = Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Full::new(Bytes::from("File output".to_string()))).unwrap();
+    accepts_html(req)
+}
+
+async fn serve_spa_fallback(fallback: &str, root: &SafeRoot, options: &ResolverOptions, req: &Parts) -> Response<ResponseBody> {
+    trace!("serving SPA fallback document: {}", fallback);
 
-            info!("{} {} \"{}\" {}",
-                  res.status(),
-                  req.method(),
-                  req.uri().path_and_query().unwrap(),
-                  "-");
+    let (file, fallback_path) = match root.open(&root.path().join(fallback.trim_start_matches('/'))).await {
+        Ok(opened) => opened,
+        Err(e) => {
+            error!("Failed to open SPA fallback document: {}", e);
+            return error_response(StatusCode::NOT_FOUND, root, options).await;
+        },
+    };
+
+    match serve_file(file.into(), &fallback_path, root, options, req).await {
+        Ok(res) => res,
+        Err(e) => {
+            error!("Failed to serve SPA fallback document: {}", e);
+            error_response(StatusCode::NOT_FOUND, root, options).await
+        },
+    }
+}
+
+/// Render the Markdown document `file`, found at `path`. `None` if it is too
+/// large to render, in which case it should be served as it is.
+async fn serve_markdown(file: &mut fs::File, path: &Path, root: &SafeRoot, options: &ResolverOptions, req: &Parts) -> io::Result<Option<Response<ResponseBody>>> {
+    let meta = file.metadata().await?;
+    if !meta.is_file() || meta.len() > markdown::MAX_SOURCE_LEN {
+        return Ok(None);
+    }
+
+    let mut source = Vec::with_capacity(meta.len() as usize);
+    file.read_to_end(&mut source).await?;
+    if options.access.refuses_private_keys() && access_policy::looks_like_private_key(&source) {
+        return Err(io::Error::new(ErrorKind::NotFound, "file looks like a private key"));
+    }
+
+    let modified = meta.modified().ok();
+    let etag = if options.strong_etags {
+        conditional::strong_etag_for(&source)
+    } else {
+        conditional::weak_etag(meta.len(), modified)
+    };
+    let rel_path = path.strip_prefix(root.path()).unwrap_or(path).to_string_lossy();
+
+    Ok(Some(markdown::rendered_response(&source, &rel_path, &etag, modified, options, req)))
+}
+
+/// Serve the already opened `file`, found at `path`, or a precompressed
+/// sibling of it.
+async fn serve_file(mut file: fs::File, path: &Path, root: &SafeRoot, options: &ResolverOptions, req: &Parts) -> io::Result<Response<ResponseBody>> {
+    let registered = options.mime_types.lookup(path);
+
+    let mut meta = file.metadata().await?;
+    if !meta.is_file() {
+        return Err(io::Error::new(ErrorKind::NotFound, "not a regular file"));
+    }
+    let mut cached = match &options.file_cache {
+        Some(cache) => cache.get(path, &mut file, &meta).await?,
+        None => None,
+    };
+
+    let mut head_len = 0;
+    if options.access.refuses_private_keys() {
+        head_len = access_policy::PRIVATE_KEY_SNIFF_LEN;
+    }
+    if registered.map_or(options.sniff, |mime| mime.starts_with("text/")) {
+        head_len = head_len.max(sniff::CHARSET_SNIFF_LEN);
+    }
+    let head = match &cached {
+        Some((entry, _)) => entry.data.slice(..head_len.min(entry.data.len())),
+        None => {
+            let mut head = Vec::with_capacity(head_len);
+            (&mut file).take(head_len as u64).read_to_end(&mut head).await?;
+            Bytes::from(head)
+        },
+    };
+
+    if options.access.refuses_private_keys() && access_policy::looks_like_private_key(&head) {
+        trace!("refusing to serve private key {:?}", path.as_os_str());
+        return Err(io::Error::new(ErrorKind::NotFound, "file looks like a private key"));
+    }
+
+    let mime = options.mime_types.content_type(path, &head, options.sniff);
+
+    let accept_encoding = req.headers.get("Accept-Encoding").and_then(|v| v.to_str().ok());
+    let accepted = encoding::accepted_encodings(accept_encoding);
+
+    let mut content_encoding = None;
+    if options.precompressed {
+        if let Some((sidecar, sidecar_path, enc)) = encoding::find_precompressed(path, root, &accepted).await {
+            trace!("serving precompressed sibling: {:?}", sidecar_path.as_os_str());
+            file = sidecar.into();
+            meta = file.metadata().await?;
+            cached = match &options.file_cache {
+                Some(cache) => cache.get(&sidecar_path, &mut file, &meta).await?,
+                None => None,
+            };
+            content_encoding = Some(enc);
+        }
+    }
+
+    let modified = meta.modified().ok();
+    let total = meta.len();
+
+    let compressible = options.compress && compression::is_compressible(&mime);
+    let compress_with = match accepted.first() {
+        Some(enc) if compressible
+            && content_encoding.is_none()
+            && total >= options.compression_min_size
+            && !req.headers.contains_key("Range") => Some(*enc),
+        _ => None,
+    };
+
+    let mut etag = if options.strong_etags {
+        match &cached {
+            Some((entry, _)) => entry.strong_etag().to_string(),
+            None => conditional::strong_etag(&mut file).await?,
+        }
+    } else {
+        conditional::weak_etag(total, modified)
+    };
+    if let Some(enc) = compress_with {
+        etag = conditional::encoded_etag(&etag, enc.token());
+    }
+    let validators = conditional::Validators {
+        etag,
+        last_modified: modified,
+    };
+
+    let mut builder = Response::builder();
+    if options.precompressed || compressible {
+        builder = builder.header("Vary", "Accept-Encoding");
+    }
+    if options.markdown && markdown::is_markdown(path) {
+        // Browsers get the rendered page instead
+        builder = builder.header("Vary", "Accept");
+    }
+    if let Some(enc) = content_encoding.or(compress_with) {
+        builder = builder.header("Content-Encoding", enc.token());
+    }
+    if let Some(directives) = options.cache.directives_for_path(path, root.path(), &mime) {
+        builder = builder.header("Cache-Control", directives);
+    }
+
+    // Files sent as they are on disk can skip the trip through userspace
+    let sendfile = req.extensions.get::<SendfileQueue>()
+        .filter(|_| compress_with.is_none())
+        .filter(|_| options.sendfile_min_size.is_some_and(|min| total >= min));
+
+    let cache_status = cached.as_ref().map(|(_, status)| *status);
+    let body_of = |parts: Vec<Part>| {
+        let body = match (&cached, sendfile, parts.as_slice()) {
+            (Some((entry, _)), _, _) => memory_body(&entry.data, parts),
+            (None, Some(queue), [Part::File { start, len }]) => match file.try_into_std() {
+                Ok(file) => SendfileBody::new(queue.clone(), file, *start, *len).boxed(),
+                Err(file) => FileBody::new(file, parts).boxed(),
+            },
+            (None, _, _) => FileBody::new(file, parts).boxed(),
+        };
+        // Only whole files are compressed, since ranges rule compression out
+        match compress_with {
+            Some(enc) => Ok(CompressedBody::new(body, enc, options.compression_level)?.boxed()),
+            None => Ok(body),
+        }
+    };
+
+    let mut res = entity_response(builder, req, &validators, total, mime, body_of)?;
+    if let Some(status) = cache_status {
+        res.extensions_mut().insert(status);
+    }
+
+    Ok(res)
+}
+
+/// Answer `req` for a representation of `total` bytes with `validators`: 304
+/// or 412 when a precondition says so, otherwise the whole representation or
+/// the ranges asked for. `builder` carries the headers particular to the
+/// resolver, and `body_of` is only called once a body is needed, with the
+/// parts to send.
+pub(crate) fn entity_response(
+    builder: response::Builder,
+    req: &Parts,
+    validators: &Validators,
+    total: u64,
+    mime: String,
+    body_of: impl FnOnce(Vec<Part>) -> io::Result<ResponseBody>,
+) -> io::Result<Response<ResponseBody>> {
+    let mut builder = builder
+        .header("ETag", &validators.etag)
+        .header("Accept-Ranges", "bytes");
+    if let Some(last_modified) = validators.last_modified_header() {
+        builder = builder.header("Last-Modified", last_modified);
+    }
+
+    let precondition = conditional::evaluate(&req.headers, &req.method, validators);
+    let range_header = req.headers.get("Range")
+        .and_then(|v| v.to_str().ok())
+        .filter(|_| req.method == Method::GET)
+        .filter(|_| match req.headers.get("If-Range").and_then(|v| v.to_str().ok()) {
+            Some(if_range) => range::if_range_matches(if_range, Some(&validators.etag), validators.last_modified),
+            None => true,
+        });
+    let ranges = match range_header {
+        Some(h) => range::parse_range(h, total),
+        None => RangeRequest::Full,
+    };
+
+    let res = match (precondition, ranges) {
+        (Precondition::NotModified, _) => builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(full_body(Bytes::new())),
+        (Precondition::Failed, _) => builder
+            .status(StatusCode::PRECONDITION_FAILED)
+            .body(full_body("Precondition failed")),
+        (Precondition::Proceed, RangeRequest::Full) => builder.status(200)
+            .header("Content-Type", mime)
+            .body(body_of(vec![Part::File { start: 0, len: total }])?),
+        (Precondition::Proceed, RangeRequest::Partial(ranges)) if ranges.len() == 1 => {
+            let r = ranges[0];
+            builder.status(StatusCode::PARTIAL_CONTENT)
+                .header("Content-Type", mime)
+                .header("Content-Range", r.content_range(total))
+                .body(body_of(vec![Part::File { start: r.start, len: r.len() }])?)
+        },
+        (Precondition::Proceed, RangeRequest::Partial(ranges)) => {
+            let boundary = range::multipart_boundary();
+            let parts = range::multipart_parts(&ranges, total, &mime, &boundary);
+            builder.status(StatusCode::PARTIAL_CONTENT)
+                .header("Content-Type", format!("multipart/byteranges; boundary={}", boundary))
+                .body(body_of(parts)?)
+        },
+        (Precondition::Proceed, RangeRequest::Unsatisfiable) => builder
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header("Content-Range", format!("bytes */{}", total))
+            .body(full_body("Range not satisfiable")),
+    }.unwrap();
+
+    Ok(res)
+}
+
+/// Methods a static file server supports, as listed in `Allow` headers.
+pub(crate) const ALLOWED_METHODS: &str = "GET, HEAD, OPTIONS";
+
+/// Answer a request that is not a GET or HEAD, or return `None` for those.
+/// OPTIONS lists the allowed methods, the other methods HTTP defines are
+/// refused and anything else is not implemented.
+pub(crate) async fn method_response(method: &Method, pages: &impl PageSource, options: &ResolverOptions) -> Option<Response<ResponseBody>> {
+    match *method {
+        Method::GET | Method::HEAD => None,
+        Method::OPTIONS => Some(Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header("Allow", ALLOWED_METHODS)
+            .body(full_body(Bytes::new()))
+            .unwrap()),
+        Method::POST | Method::PUT | Method::DELETE | Method::PATCH | Method::CONNECT | Method::TRACE => {
+            let mut res = error_response(StatusCode::METHOD_NOT_ALLOWED, pages, options).await;
+            res.headers_mut().insert("Allow", HeaderValue::from_static(ALLOWED_METHODS));
+            Some(res)
+        },
+        _ => Some(error_response(StatusCode::NOT_IMPLEMENTED, pages, options).await),
+    }
+}
+
+/// Dispatch on the request method, then resolve the path for GET and HEAD.
+/// Returns the response and the decoded path to log.
+async fn respond(root: &SafeRoot, options: &ResolverOptions, req: &Parts, prefix: &str) -> (Response<ResponseBody>, String) {
+    let raw_path = req.uri.path().to_string();
+
+    if let Some(res) = method_response(&req.method, root, options).await {
+        return (res, raw_path);
+    }
+
+    let (res, path) = match util::normalize_request_path(&raw_path, options.allow_encoded_slashes) {
+        Ok(path) => {
+            let res = match mount_table::mount_relative(&path, prefix) {
+                Some("") if options.slash_redirects => redirect(req, &format!("{}/", prefix)),
+                Some("") => resolve(root, options, req, prefix, "/").await,
+                Some(rel) => resolve(root, options, req, prefix, rel).await,
+                None => error_response(StatusCode::NOT_FOUND, root, options).await,
+            };
+            (res, path)
+        },
+        Err(e) => {
+            trace!("rejected request path: {:?}", e);
+            (error_response(StatusCode::BAD_REQUEST, root, options).await, raw_path)
+        },
+    };
+
+    if req.method == Method::HEAD {
+        return (without_body(res), path);
+    }
+
+    (res, path)
+}
+
+/// Strip the body from a response to a HEAD request, keeping the
+/// `Content-Length` a GET would have produced when it is known up front.
+pub(crate) fn without_body(res: Response<ResponseBody>) -> Response<ResponseBody> {
+    let (mut parts, body) = res.into_parts();
+    if let Some(len) = body.size_hint().exact() {
+        if !parts.headers.contains_key("Content-Length") {
+            parts.headers.insert("Content-Length", HeaderValue::from(len));
+        }
+    }
+
+    Response::from_parts(parts, full_body(Bytes::new()))
+}
+
+/// Write the request log line for `res`, answering `req` for the decoded
+/// `path`, followed by `note`.
+pub(crate) fn log_response(req: &Parts, path: &str, res: &Response<ResponseBody>, note: &str) {
+    let query = match req.uri.query() {
+        Some(q) => format!("?{}", q),
+        None => String::new(),
+    };
+    let size = match res.body().size_hint().exact() {
+        Some(n) => n.to_string(),
+        None => "-".to_string(),
+    };
+
+    info!("{} {} \"{}{}\" {}{}",
+          res.status(),
+          req.method,
+          path,
+          query,
+          size,
+          note);
+}
+
+impl Service<Request<Incoming>> for FileResolver {
+    type Response = Response<ResponseBody>;
+    type Error = hyper::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn call(&mut self, req: Request<Incoming>) -> Self::Future {
+        let root = self.root.clone();
+        let options = Arc::clone(&self.options);
+        let prefix = self.prefix.clone();
+        let (parts, _body) = req.into_parts();
+
+        Box::pin(async move {
+            let (mut res, path) = respond(&root, &options, &parts, &prefix).await;
+            if options.cache.no_store() {
+                res.headers_mut().insert("Cache-Control", HeaderValue::from_static("no-store"));
+            }
+            if !options.sniff {
+                res.headers_mut().insert("X-Content-Type-Options", HeaderValue::from_static("nosniff"));
+            }
+
+            let cache = match (res.extensions().get::<CacheStatus>(), &options.file_cache) {
+                (Some(status), Some(cache)) => format!(" (cache {}, {} hits / {} misses)",
+                    if *status == CacheStatus::Hit { "hit" } else { "miss" },
+                    cache.hits(),
+                    cache.misses()),
+                _ => String::new(),
+            };
+            log_response(&parts, &path, &res, &cache);
 
             Ok(res)
         })
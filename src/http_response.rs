@@ -1,3 +1,4 @@
+use crate::error_pages;
 use std::collections::HashMap;
 
 pub struct HttpResponse {
@@ -21,61 +22,40 @@ impl HttpResponse {
         }
     }
 
-    pub fn bad_request(version: &str, method: &str) -> Self {
+    fn error_page(version: &str, method: &str, status: u16, status_text: &str) -> Self {
+        let body = error_pages::builtin_page(status, status_text);
+
         let mut res = HttpResponse::new(version, method);
-        res.status = 400u16;
-        res.status_text = String::from("Bad Request");
-        res.headers.insert(String::from("content-type"), String::from("text/plain; charset=utf-8"));
-        res.headers.insert(String::from("content-length"), String::from("11"));
-        res.body = Some("Bad Request".into());
+        res.status = status;
+        res.status_text = String::from(status_text);
+        res.headers.insert(String::from("content-type"), String::from("text/html; charset=utf-8"));
+        res.headers.insert(String::from("content-length"), body.len().to_string());
+        res.body = Some(body.into());
 
         res
     }
 
-    pub fn unauthorized(version: &str, method: &str) -> Self {
-        let mut res = HttpResponse::new(version, method);
-        res.status = 401u16;
-        res.status_text = String::from("Unauthorized");
-        res.headers.insert(String::from("content-type"), String::from("text/plain; charset=utf-8"));
-        res.headers.insert(String::from("content-length"), String::from("12"));
-        res.body = Some("Unauthorized".into());
+    pub fn bad_request(version: &str, method: &str) -> Self {
+        HttpResponse::error_page(version, method, 400u16, "Bad Request")
+    }
 
-        res
+    pub fn unauthorized(version: &str, method: &str) -> Self {
+        HttpResponse::error_page(version, method, 401u16, "Unauthorized")
     }
 
     pub fn forbidden(version: &str, method: &str) -> Self {
-        let mut res = HttpResponse::new(version, method);
-        res.status = 403u16;
-        res.status_text = String::from("Forbidden");
-        res.headers.insert(String::from("content-type"), String::from("text/plain; charset=utf-8"));
-        res.headers.insert(String::from("content-length"), String::from("9"));
-        res.body = Some("Forbidden".into());
-
-        res
+        HttpResponse::error_page(version, method, 403u16, "Forbidden")
     }
 
     pub fn not_found(version: &str, method: &str) -> Self {
-        let mut res = HttpResponse::new(version, method);
-        res.status = 404u16;
-        res.status_text = String::from("Not Found");
-        res.headers.insert(String::from("content-type"), String::from("text/plain; charset=utf-8"));
-        res.headers.insert(String::from("content-length"), String::from("9"));
-        res.body = Some("Not Found".into());
-
-        res
+        HttpResponse::error_page(version, method, 404u16, "Not Found")
     }
 
     pub fn server_error(version: &str, method: &str) -> Self {
-        let mut res = HttpResponse::new(version, method);
-        res.status = 500u16;
-        res.status_text = String::from("Server Error");
-        res.headers.insert(String::from("content-type"), String::from("text/plain; charset=utf-8"));
-        res.headers.insert(String::from("content-length"), String::from("12"));
-        res.body = Some("Server Error".into());
-
-        res
+        HttpResponse::error_page(version, method, 500u16, "Server Error")
     }
 
+    #[allow(clippy::single_match)]
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut out = format!("{} {} {}\r\n", self.http_version, self.status, self.status_text);
 
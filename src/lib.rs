@@ -6,9 +6,46 @@ pub mod util;
 
 pub mod work_queue;
 
+mod access_policy;
+mod archive_root;
+mod cache_control;
+mod compression;
+mod conditional;
+mod directory_listing;
+mod embedded;
+pub use embedded::{write_bundle, BundleOptions};
+mod encoding;
+mod error_pages;
+
+mod file_body;
+pub use file_body::ResponseBody;
+mod file_cache;
+
 mod file_resolver;
+mod markdown;
+mod mime_registry;
+mod mount_table;
+mod range;
+mod safe_open;
+mod sniff;
+mod ssi;
+mod virtual_hosts;
+mod zero_copy;
+pub use zero_copy::{serve_zero_copy, ZeroCopy, ZeroCopyStream};
+
 pub mod responders {
-    pub use crate::file_resolver::FileResolver;
+    pub use crate::access_policy::AccessPolicy;
+    pub use crate::archive_root::ArchiveResolver;
+    pub use crate::cache_control::{CachePolicy, CacheRule};
+    pub use crate::embedded::{EmbeddedFile, EmbeddedResolver};
+    pub use crate::file_cache::FileCache;
+    pub use crate::file_resolver::{FileResolver, ResolverOptions};
+    pub use crate::markdown::DEFAULT_TEMPLATE as DEFAULT_MARKDOWN_TEMPLATE;
+    pub use crate::mime_registry::{MimeRegistry, DEFAULT_MIME};
+    pub use crate::mount_table::MountTable;
+    pub use crate::safe_open::SymlinkPolicy;
+    pub use crate::ssi::ServerIncludes;
+    pub use crate::virtual_hosts::VirtualHosts;
 }
 
 mod http_request;
@@ -25,4 +62,4 @@ pub trait Responder {
 }
 
 mod command_line;
-pub use command_line::{CommandLine, Parser};
+pub use command_line::{CommandLine, Parser, SiteArg};
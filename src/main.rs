@@ -1,10 +1,10 @@
-use eyre::Result;
+use eyre::{eyre, Result};
 use hyper::server::conn::http1;
 use qsrv::{
-    responders::FileResolver,
-    CommandLine, Parser,
+    responders::{AccessPolicy, ArchiveResolver, CachePolicy, CacheRule, FileCache, FileResolver, MimeRegistry, MountTable, ResolverOptions, ServerIncludes, VirtualHosts},
+    serve_zero_copy, CommandLine, Parser, SiteArg,
 };
-use std::net::SocketAddr;
+use std::{error::Error, net::SocketAddr, path::Path};
 use time::macros::format_description;
 use tokio::net::TcpListener;
 use tracing::{error, info, Level};
@@ -39,9 +39,97 @@ async fn main() -> Result<()> {
         tracing::subscriber::set_global_default(subscriber)?;
     }
 
-    // let path = Arc::new(args.document_root.unwrap_or(".".into()).clone());
-    let path = args.document_root.unwrap_or(".".into());
-    info!("document root set to \"{}\"", path);
+    let defaults = ResolverOptions::default();
+    let mut error_pages = defaults.error_pages.clone();
+    error_pages.extend(args.error_page);
+    let mut cache_rules = Vec::new();
+    for (glob, directives) in &args.cache_control {
+        cache_rules.push(CacheRule::glob(glob, directives)?);
+    }
+    for (mime, directives) in &args.cache_control_type {
+        cache_rules.push(CacheRule::mime(mime, directives));
+    }
+    let mut mime_types = MimeRegistry::new();
+    if let Some(file) = &args.mime_types {
+        mime_types.load_mime_types(Path::new(file))?;
+    }
+    for (ext, mime) in &args.mime_type {
+        mime_types.insert(ext, mime);
+    }
+    let markdown_template = match &args.markdown_template {
+        Some(file) => Some(std::fs::read_to_string(file)
+            .map_err(|e| eyre!("markdown template \"{}\": {}", file, e))?),
+        None => None,
+    };
+    let includes = if args.ssi.is_empty() {
+        None
+    } else {
+        let mut includes = ServerIncludes::new(&args.ssi)?.with_env(&args.ssi_env);
+        if let Some(file) = &args.ssi_data {
+            let data = std::fs::read_to_string(file)
+                .map_err(|e| eyre!("SSI data \"{}\": {}", file, e))?;
+            includes = includes.with_data(serde_json::from_str(&data)
+                .map_err(|e| eyre!("SSI data \"{}\": {}", file, e))?);
+        }
+        Some(includes)
+    };
+    let options = ResolverOptions {
+        autoindex: args.autoindex,
+        strong_etags: args.strong_etags,
+        precompressed: args.precompressed,
+        compress: args.compress,
+        compression_level: args.compression_level,
+        compression_min_size: args.compression_min_size.unwrap_or(defaults.compression_min_size),
+        spa_fallback: args.spa,
+        error_pages,
+        allow_encoded_slashes: args.allow_encoded_slashes,
+        index_files: if args.index.is_empty() { defaults.index_files.clone() } else { args.index },
+        clean_urls: args.clean_urls,
+        slash_redirects: !args.no_slash_redirects,
+        access: AccessPolicy::new(args.allow_hidden, &args.allow, &args.deny, args.refuse_private_keys)?,
+        symlinks: args.symlinks,
+        cache: CachePolicy::new(cache_rules, !args.no_hashed_immutable, args.no_cache),
+        mime_types,
+        file_cache: args.file_cache.then(|| FileCache::new(
+            args.file_cache_entries.unwrap_or(FileCache::DEFAULT_MAX_ENTRIES),
+            args.file_cache_size.unwrap_or(FileCache::DEFAULT_MAX_BYTES),
+            args.file_cache_max_file.unwrap_or(FileCache::DEFAULT_MAX_FILE_SIZE),
+        )),
+        sniff: args.sniff,
+        sendfile_min_size: args.sendfile_min_size.or(defaults.sendfile_min_size),
+        markdown: args.markdown || markdown_template.is_some(),
+        markdown_template,
+        includes,
+    };
+
+    let mut mounts = MountTable::new();
+    if args.document_root.is_some() || (args.mount.is_empty() && args.vhost.is_empty()) {
+        let path = args.document_root.unwrap_or(".".into());
+        info!("document root set to \"{}\"", path);
+        mount_path(&mut mounts, "/", &path, options.clone())
+            .map_err(|e| eyre!("document root \"{}\": {}", path, e))?;
+    }
+    for mount in args.mount {
+        info!("mounting \"{}\" at {}", mount.path, mount.name);
+        mount_site(&mut mounts, &mount.name, &mount, &options)?;
+    }
+
+    let mut sites = VirtualHosts::new();
+    if !mounts.is_empty() {
+        sites.set_default(mounts);
+    }
+    for vhost in args.vhost {
+        info!("serving host {} from \"{}\"", vhost.name, vhost.path);
+        let mut site = MountTable::new();
+        mount_site(&mut site, "/", &vhost, &options)?;
+        sites.add_host(&vhost.name, site);
+    }
+    if let Some(host) = args.default_host {
+        let site = sites.site(&host)
+            .ok_or_else(|| eyre!("--default-host {} does not match any --vhost", host))?
+            .clone();
+        sites.set_default(site);
+    }
 
     let addr = SocketAddr::from(([0, 0, 0, 0], args.port.unwrap_or(3000)));
     let listener = TcpListener::bind(addr).await?;
@@ -50,16 +138,51 @@ async fn main() -> Result<()> {
     loop {
         let (stream, _) = listener.accept().await?;
 
-        let root_path = path.clone();
-        tokio::task::spawn(async move {
-            let svc = FileResolver::new(&root_path).unwrap();
-            if let Err(e) = http1::Builder::new()
-                .serve_connection(stream, svc).await
-            {
-                error!("Error serving connection: {:?}", e);
-            }
-        });
+        let svc = sites.clone();
+        if args.sendfile {
+            let conn = serve_zero_copy(http1::Builder::new(), stream, svc);
+            tokio::task::spawn(async move {
+                if let Err(e) = conn.await {
+                    error!("Error serving connection: {:?}", e);
+                }
+            });
+        } else {
+            tokio::task::spawn(async move {
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(stream, svc).await
+                {
+                    error!("Error serving connection: {:?}", e);
+                }
+            });
+        }
     }
 
     // Ok(())
 }
+
+/// Mount the directory or archive at `path`, picking the resolver by
+/// whether it is an archive file.
+fn mount_path(table: &mut MountTable, prefix: &str, path: &str, options: ResolverOptions) -> Result<(), Box<dyn Error>> {
+    if ArchiveResolver::is_archive(Path::new(path)) && Path::new(path).is_file() {
+        table.mount_archive(prefix, ArchiveResolver::with_options(path, options)?);
+    } else {
+        table.mount(prefix, FileResolver::with_options(path, options)?);
+    }
+
+    Ok(())
+}
+
+/// Mount a `--mount` or `--vhost` at `prefix`, applying its options on top
+/// of the global ones.
+fn mount_site(table: &mut MountTable, prefix: &str, site: &SiteArg, options: &ResolverOptions) -> Result<()> {
+    let mut options = options.clone();
+    if let Some(autoindex) = site.autoindex {
+        options.autoindex = autoindex;
+    }
+    if !site.index.is_empty() {
+        options.index_files = site.index.clone();
+    }
+
+    mount_path(table, prefix, &site.path, options)
+        .map_err(|e| eyre!("\"{}\": {}", site.path, e))
+}
@@ -0,0 +1,162 @@
+use crate::{
+    conditional::{self, Precondition},
+    file_body::{full_body, ResponseBody},
+    file_resolver::{self, ResolverOptions},
+    util,
+};
+use hyper::{body::Bytes, http::request::Parts, Response, StatusCode};
+use pulldown_cmark::{html, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use std::{path::Path, time::SystemTime};
+
+/// Name of the document used as a directory index when there is no index
+/// file and Markdown rendering is on.
+pub(crate) const README: &str = "README.md";
+
+/// Sources larger than this are always sent as they are.
+pub(crate) const MAX_SOURCE_LEN: u64 = 4 * 1024 * 1024;
+
+/// Template used unless one is configured. `{{title}}` is replaced with the
+/// escaped document title and `{{content}}` with the rendered HTML.
+pub const DEFAULT_TEMPLATE: &str = "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>{{title}}</title>
+<style>
+:root { color-scheme: light dark; }
+body { font-family: sans-serif; line-height: 1.6; max-width: 50em; margin: 2em auto; padding: 0 1em; }
+pre { padding: 1em; overflow: auto; background: rgba(127, 127, 127, 0.1); }
+code { font-family: monospace; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid rgba(127, 127, 127, 0.4); padding: 0.3em 0.8em; }
+blockquote { margin-left: 0; padding-left: 1em; border-left: 0.25em solid rgba(127, 127, 127, 0.4); }
+img { max-width: 100%; }
+</style>
+</head>
+<body>
+<article class=\"markdown-body\">
+{{content}}
+</article>
+</body>
+</html>
+";
+
+/// Whether `path` has a Markdown extension.
+pub(crate) fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown"))
+}
+
+/// Whether a Markdown document should be rendered for `req`: the client is
+/// a browser and did not ask for the source with `?raw`.
+pub(crate) fn wants_rendered(req: &Parts) -> bool {
+    let params = util::parse_query(req.uri.query().unwrap_or(""));
+
+    !params.contains_key("raw") && file_resolver::accepts_html(req)
+}
+
+/// The text of the first heading of a document, if it has one.
+fn first_heading(source: &str) -> Option<String> {
+    let mut title = None;
+    for event in Parser::new(source) {
+        match (event, &mut title) {
+            (Event::Start(Tag::Heading { level: HeadingLevel::H1, .. }), None) => title = Some(String::new()),
+            (Event::Text(text) | Event::Code(text), Some(title)) => title.push_str(&text),
+            (Event::End(TagEnd::Heading(_)), Some(_)) => break,
+            _ => (),
+        }
+    }
+
+    title.filter(|t| !t.trim().is_empty())
+}
+
+/// URL schemes that run script when a link is followed.
+const SCRIPT_SCHEMES: [&str; 3] = ["javascript:", "vbscript:", "data:"];
+
+fn is_script_url(url: &str) -> bool {
+    let url: String = url.chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+
+    SCRIPT_SCHEMES.iter().any(|scheme| url.starts_with(scheme))
+}
+
+/// Documents are not trusted to run script in the pages they are rendered
+/// into: raw HTML is shown as text and links to script URLs lose their
+/// target.
+fn defuse(event: Event) -> Event {
+    match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        Event::Start(Tag::HtmlBlock) => Event::Start(Tag::Paragraph),
+        Event::End(TagEnd::HtmlBlock) => Event::End(TagEnd::Paragraph),
+        Event::Start(Tag::Link { link_type, dest_url, title, id }) if is_script_url(&dest_url) => {
+            Event::Start(Tag::Link { link_type, dest_url: "#".into(), title, id })
+        },
+        event => event,
+    }
+}
+
+/// Render `source` as CommonMark with the GitHub extensions (tables, task
+/// lists, strikethrough, footnotes) and put it into `template`. Fenced code
+/// blocks get a `language-*` class for client-side highlighters.
+pub(crate) fn render(source: &str, name: &str, template: Option<&str>) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_GFM;
+
+    let mut content = String::with_capacity(source.len() * 3 / 2);
+    html::push_html(&mut content, Parser::new_ext(source, options).map(defuse));
+    let title = first_heading(source).unwrap_or_else(|| name.to_string());
+
+    template.unwrap_or(DEFAULT_TEMPLATE)
+        .replace("{{title}}", &util::html_escape(&title))
+        .replace("{{content}}", &content)
+}
+
+/// Respond with the rendered form of the Markdown document `source`, found
+/// at `rel_path` below the root and validated by `etag` and `modified`.
+pub(crate) fn rendered_response(
+    source: &[u8],
+    rel_path: &str,
+    etag: &str,
+    modified: Option<SystemTime>,
+    options: &ResolverOptions,
+    req: &Parts,
+) -> Response<ResponseBody> {
+    let mime = "text/html; charset=utf-8";
+    let validators = conditional::Validators {
+        etag: conditional::encoded_etag(etag, "html"),
+        last_modified: modified,
+    };
+
+    let mut builder = Response::builder()
+        .header("ETag", &validators.etag)
+        .header("Vary", "Accept");
+    if let Some(last_modified) = validators.last_modified_header() {
+        builder = builder.header("Last-Modified", last_modified);
+    }
+    if let Some(directives) = options.cache.directives(rel_path, mime) {
+        builder = builder.header("Cache-Control", directives);
+    }
+
+    match conditional::evaluate(&req.headers, &req.method, &validators) {
+        Precondition::NotModified => builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(full_body(Bytes::new())),
+        Precondition::Failed => builder
+            .status(StatusCode::PRECONDITION_FAILED)
+            .body(full_body("Precondition failed")),
+        Precondition::Proceed => {
+            let name = rel_path.rsplit('/').next().unwrap_or(rel_path);
+            let html = render(&String::from_utf8_lossy(source), name, options.markdown_template.as_deref());
+            builder.status(200)
+                .header("Content-Type", mime)
+                .body(full_body(html))
+        },
+    }.unwrap()
+}
@@ -0,0 +1,192 @@
+use crate::sniff;
+use std::{collections::HashMap, fs, io, path::Path};
+
+/// Type sent for files whose extension is not registered.
+pub const DEFAULT_MIME: &str = "application/octet-stream";
+
+/// Types known without any configuration. Multi-part extensions are listed
+/// without their leading dot.
+const BUILTIN_TYPES: &[(&str, &str)] = &[
+    ("aac", "audio/aac"),
+    ("abw", "application/x-abiword"),
+    ("apng", "image/apng"),
+    ("arc", "application/x-freearc"),
+    ("avif", "image/avif"),
+    ("avi", "video/x-msvideo"),
+    ("azw", "application/vnd.amazon.ebook"),
+    ("bmp", "image/bmp"),
+    ("bz", "application/x-bzip"),
+    ("bz2", "application/x-bzip2"),
+    ("cda", "application/x-cdf"),
+    ("csh", "application/x-csh"),
+    ("css", "text/css"),
+    ("csv", "text/csv"),
+    ("doc", "application/msword"),
+    ("docx", "application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+    ("eot", "application/vnd.ms-fontobject"),
+    ("epub", "application/epub+zip"),
+    ("gz", "application/gzip"),
+    ("gif", "image/gif"),
+    ("htm", "text/html"),
+    ("html", "text/html"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("ics", "text/calendar"),
+    ("jar", "application/java-archive"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("js", "text/javascript"),
+    ("mjs", "text/javascript"),
+    ("json", "application/json"),
+    ("jsonld", "application/ld+json"),
+    ("m3u8", "application/vnd.apple.mpegurl"),
+    ("m4a", "audio/mp4"),
+    ("map", "application/json"),
+    ("md", "text/markdown"),
+    ("markdown", "text/markdown"),
+    ("mid", "audio/midi"),
+    ("midi", "audio/midi"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("mpg", "video/mpeg"),
+    ("mpeg", "video/mpeg"),
+    ("mpkg", "application/vnd.apple.installer+xml"),
+    ("odp", "application/vnd.oasis.opendocument.presentation"),
+    ("ods", "application/vnd.oasis.opendocument.spreadsheet"),
+    ("odt", "application/vnd.oasis.opendocument.text"),
+    ("oga", "audio/ogg"),
+    ("ogg", "audio/ogg"),
+    ("ogv", "video/ogg"),
+    ("ogx", "application/ogg"),
+    ("opus", "audio/opus"),
+    ("otf", "font/otf"),
+    ("png", "image/png"),
+    ("pdf", "application/pdf"),
+    ("php", "application/x-httpd-php"),
+    ("ppt", "application/vnd.ms-powerpoint"),
+    ("pptx", "application/vnd.openxmlformats-officedocument.presentationml.presentation"),
+    ("rar", "application/vnd.rar"),
+    ("rtf", "application/rtf"),
+    ("sh", "application/x-sh"),
+    ("shtml", "text/html"),
+    ("svg", "image/svg+xml"),
+    ("tar", "application/x-tar"),
+    ("tar.gz", "application/x-gtar"),
+    ("tgz", "application/x-gtar"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("toml", "application/toml"),
+    ("ts", "video/mp2t"),
+    ("ttf", "font/ttf"),
+    ("txt", "text/plain"),
+    ("vsd", "application/vnd.visio"),
+    ("wasm", "application/wasm"),
+    ("wav", "audio/wav"),
+    ("weba", "audio/webm"),
+    ("webm", "video/webm"),
+    ("webmanifest", "application/manifest+json"),
+    ("webp", "image/webp"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("xhtml", "application/xhtml+xml"),
+    ("xls", "application/vnd.ms-excel"),
+    ("xlsx", "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+    ("xml", "application/xml"),
+    ("xul", "application/vnd.mozilla.xul+xml"),
+    ("xz", "application/x-xz"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("zip", "application/zip"),
+    ("zst", "application/zstd"),
+    ("7z", "application/x-7z-compressed"),
+];
+
+/// Maps file extensions to MIME types. Extensions are matched without regard
+/// to case, and the longest registered suffix wins, so `.tar.gz` can be given
+/// a different type than `.gz`.
+#[derive(Clone, Debug)]
+pub struct MimeRegistry {
+    types: HashMap<String, String>,
+}
+
+impl MimeRegistry {
+    /// A registry holding the built-in table.
+    pub fn new() -> Self {
+        let mut registry = MimeRegistry::empty();
+        for (ext, mime) in BUILTIN_TYPES.iter() {
+            registry.insert(ext, mime);
+        }
+
+        registry
+    }
+
+    /// A registry without any types.
+    pub fn empty() -> Self {
+        MimeRegistry { types: HashMap::new() }
+    }
+
+    /// Register `mime` for `ext`, replacing any earlier type. A leading dot
+    /// is ignored, so `.tar.gz` and `tar.gz` are the same extension.
+    pub fn insert(&mut self, ext: &str, mime: &str) {
+        let ext = ext.trim_start_matches('.').to_ascii_lowercase();
+        if !ext.is_empty() {
+            self.types.insert(ext, mime.to_string());
+        }
+    }
+
+    /// Register every type listed in `contents`, which uses the format of
+    /// `/etc/mime.types`: a type followed by its extensions on each line,
+    /// with `#` starting a comment.
+    pub fn parse_mime_types(&mut self, contents: &str) {
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or("");
+            let mut fields = line.split_whitespace();
+            let mime = match fields.next() {
+                Some(m) if m.contains('/') => m,
+                _ => continue,
+            };
+            for ext in fields {
+                self.insert(ext, mime);
+            }
+        }
+    }
+
+    /// Read a `/etc/mime.types`-format file, see
+    /// [`MimeRegistry::parse_mime_types`].
+    pub fn load_mime_types(&mut self, path: &Path) -> io::Result<()> {
+        let contents = fs::read_to_string(path)?;
+        self.parse_mime_types(&contents);
+
+        Ok(())
+    }
+
+    /// The registered type for the extension of `path`, if any.
+    pub fn lookup(&self, path: &Path) -> Option<&str> {
+        let name = path.file_name()?.to_str()?;
+        // Like Path::extension, a leading dot does not start an extension
+        let name = name.strip_prefix('.').unwrap_or(name).to_ascii_lowercase();
+
+        name.match_indices('.')
+            .map(|(idx, _)| &name[idx + 1..])
+            .find_map(|ext| self.types.get(ext))
+            .map(String::as_str)
+    }
+
+    /// The `Content-Type` for `path`, whose contents start with `head`: the
+    /// registered type, the type sniffed from `head` if `sniff` is set, or
+    /// [`DEFAULT_MIME`]. Text types get the charset `head` is encoded in.
+    pub fn content_type(&self, path: &Path, head: &[u8], sniff: bool) -> String {
+        let mime = match self.lookup(path) {
+            Some(mime) => mime,
+            None if sniff => sniff::sniff(head),
+            None => DEFAULT_MIME,
+        };
+
+        sniff::with_charset(mime, head)
+    }
+}
+
+impl Default for MimeRegistry {
+    fn default() -> Self {
+        MimeRegistry::new()
+    }
+}
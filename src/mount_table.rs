@@ -0,0 +1,136 @@
+use crate::{
+    archive_root::ArchiveResolver,
+    embedded::EmbeddedResolver,
+    error_pages::builtin_response,
+    file_body::ResponseBody,
+    file_resolver::FileResolver,
+    util,
+};
+use hyper::{
+    body::{Body, Incoming},
+    service::Service,
+    Request, Response, StatusCode,
+};
+use std::{cmp::Reverse, future::Future, pin::Pin};
+use tracing::info;
+
+/// The part of the decoded request `path` below the mount point at `prefix`
+/// (given without a trailing slash), if the prefix matches whole segments.
+/// A request for the mount point itself yields an empty string.
+pub(crate) fn mount_relative<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    match path.strip_prefix(prefix) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => Some(rest),
+        _ => None,
+    }
+}
+
+/// Log a response produced before any resolver was picked, in the same
+/// format the resolvers use.
+pub(crate) fn log_unrouted(req: &Request<Incoming>, res: &Response<ResponseBody>) {
+    let size = match res.body().size_hint().exact() {
+        Some(n) => n.to_string(),
+        None => "-".to_string(),
+    };
+
+    info!("{} {} \"{}\" {}", res.status(), req.method(), req.uri(), size);
+}
+
+/// What is mounted at a prefix.
+#[derive(Clone)]
+enum Mount {
+    Files(FileResolver),
+    Archive(ArchiveResolver),
+}
+
+impl Mount {
+    fn prefix(&self) -> &str {
+        match self {
+            Mount::Files(resolver) => &resolver.prefix,
+            Mount::Archive(resolver) => &resolver.prefix,
+        }
+    }
+
+    fn set_prefix(&mut self, prefix: &str) {
+        let mut prefix = format!("/{}", prefix.trim_matches('/'));
+        if prefix == "/" {
+            prefix.clear();
+        }
+
+        match self {
+            Mount::Files(resolver) => resolver.prefix = prefix,
+            Mount::Archive(resolver) => resolver.prefix = prefix,
+        }
+    }
+}
+
+/// Dispatches requests to one [`FileResolver`], [`ArchiveResolver`] or
+/// [`EmbeddedResolver`] per URL prefix. The longest prefix that matches the
+/// request path wins, so `/assets` can be served from a different directory
+/// than `/`.
+#[derive(Clone, Default)]
+pub struct MountTable {
+    mounts: Vec<Mount>,
+}
+
+impl MountTable {
+    pub fn new() -> Self {
+        MountTable::default()
+    }
+
+    /// Serve requests below `prefix` (e.g. `/assets`) from `resolver`,
+    /// replacing any resolver already mounted there.
+    pub fn mount(&mut self, prefix: &str, resolver: FileResolver) {
+        self.insert(prefix, Mount::Files(resolver));
+    }
+
+    /// Serve requests below `prefix` from the archive behind `resolver`,
+    /// replacing any resolver already mounted there.
+    pub fn mount_archive(&mut self, prefix: &str, resolver: ArchiveResolver) {
+        self.insert(prefix, Mount::Archive(resolver));
+    }
+
+    /// Serve requests below `prefix` from embedded files, replacing any
+    /// resolver already mounted there.
+    pub fn mount_embedded(&mut self, prefix: &str, resolver: EmbeddedResolver) {
+        self.insert(prefix, Mount::Archive(resolver.0));
+    }
+
+    fn insert(&mut self, prefix: &str, mut mount: Mount) {
+        mount.set_prefix(prefix);
+
+        self.mounts.retain(|m| m.prefix() != mount.prefix());
+        self.mounts.push(mount);
+        self.mounts.sort_by_key(|m| Reverse(m.prefix().len()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mounts.is_empty()
+    }
+}
+
+impl Service<Request<Incoming>> for MountTable {
+    type Response = Response<ResponseBody>;
+    type Error = hyper::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn call(&mut self, req: Request<Incoming>) -> Self::Future {
+        // Dispatch on the normalized path so `/assets/../x` is not handed to
+        // the /assets mount. Each resolver normalizes again with its own
+        // settings.
+        let path = util::normalize_request_path(req.uri().path(), true);
+        let mount = path.as_ref().ok().and_then(|path| {
+            self.mounts.iter_mut().find(|m| mount_relative(path, m.prefix()).is_some())
+        });
+        match mount {
+            Some(Mount::Files(resolver)) => return resolver.call(req),
+            Some(Mount::Archive(resolver)) => return resolver.call(req),
+            None => (),
+        }
+
+        let status = if path.is_ok() { StatusCode::NOT_FOUND } else { StatusCode::BAD_REQUEST };
+        let res = builtin_response(status);
+        log_unrouted(&req, &res);
+
+        Box::pin(async move { Ok(res) })
+    }
+}
@@ -0,0 +1,163 @@
+use crate::file_body::Part;
+use hyper::body::Bytes;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Requests with more ranges than this are served in full rather than as a
+/// (potentially very large) multipart response.
+const MAX_RANGES: usize = 32;
+
+/// An inclusive byte range within a representation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+pub(crate) enum RangeRequest {
+    /// No usable Range header; serve the whole representation
+    Full,
+    /// One or more satisfiable ranges
+    Partial(Vec<ByteRange>),
+    /// A syntactically valid Range header that selects nothing
+    Unsatisfiable,
+}
+
+/// Parse a `Range` header against a representation of `len` bytes. Headers
+/// that cannot be parsed or use an unknown unit are ignored as RFC 9110
+/// allows.
+pub(crate) fn parse_range(header: &str, len: u64) -> RangeRequest {
+    let specs = match header.trim().split_once('=') {
+        Some((unit, specs)) if unit.trim().eq_ignore_ascii_case("bytes") => specs,
+        _ => return RangeRequest::Full,
+    };
+
+    let mut ranges = Vec::new();
+    let mut any = false;
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        any = true;
+        let (first, last) = match spec.split_once('-') {
+            Some(parts) => parts,
+            None => return RangeRequest::Full,
+        };
+
+        let range = if first.is_empty() {
+            // Suffix range: the final `last` bytes
+            let suffix = match parse_pos(last) {
+                Some(n) => n,
+                None => return RangeRequest::Full,
+            };
+            if suffix == 0 || len == 0 {
+                continue;
+            }
+            ByteRange {
+                start: len.saturating_sub(suffix),
+                end: len - 1,
+            }
+        } else {
+            let start = match parse_pos(first) {
+                Some(n) => n,
+                None => return RangeRequest::Full,
+            };
+            let end = if last.is_empty() {
+                u64::MAX
+            } else {
+                match parse_pos(last) {
+                    Some(n) if n >= start => n,
+                    _ => return RangeRequest::Full,
+                }
+            };
+            if start >= len {
+                continue;
+            }
+            ByteRange {
+                start,
+                end: end.min(len - 1),
+            }
+        };
+
+        ranges.push(range);
+    }
+
+    if !any {
+        // `bytes=` without a single range is malformed, not unsatisfiable
+        return RangeRequest::Full;
+    }
+    if ranges.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+    if ranges.len() > MAX_RANGES {
+        return RangeRequest::Full;
+    }
+
+    RangeRequest::Partial(ranges)
+}
+
+/// Parse a byte position. Positions too large for a `u64` lie past the end of
+/// any file, so they saturate instead of invalidating the whole header.
+fn parse_pos(s: &str) -> Option<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    Some(s.parse::<u64>().unwrap_or(u64::MAX))
+}
+
+/// Evaluate an `If-Range` header. Entity tags must match strongly; dates must
+/// match the modification time exactly (at one second resolution).
+pub(crate) fn if_range_matches(value: &str, etag: Option<&str>, modified: Option<SystemTime>) -> bool {
+    let value = value.trim();
+    if value.starts_with('"') || value.starts_with("W/") {
+        return match etag {
+            Some(etag) => !value.starts_with("W/") && !etag.starts_with("W/") && value == etag,
+            None => false,
+        };
+    }
+
+    match (httpdate::parse_http_date(value), modified) {
+        (Ok(date), Some(modified)) => unix_seconds(date) == unix_seconds(modified),
+        _ => false,
+    }
+}
+
+fn unix_seconds(t: SystemTime) -> Option<u64> {
+    t.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+/// Generate a boundary for `multipart/byteranges` bodies.
+pub(crate) fn multipart_boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+
+    format!("qsrv-{:x}", nanos)
+}
+
+/// Build the parts of a `multipart/byteranges` body for `ranges` of a file
+/// that is `total` bytes long.
+pub(crate) fn multipart_parts(ranges: &[ByteRange], total: u64, mime: &str, boundary: &str) -> Vec<Part> {
+    let mut parts = Vec::with_capacity(ranges.len() * 3 + 1);
+
+    for range in ranges {
+        parts.push(Part::Bytes(Bytes::from(format!(
+            "--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+            boundary,
+            mime,
+            range.content_range(total)))));
+        parts.push(Part::File { start: range.start, len: range.len() });
+        parts.push(Part::Bytes(Bytes::from_static(b"\r\n")));
+    }
+    parts.push(Part::Bytes(Bytes::from(format!("--{}--\r\n", boundary))));
+
+    parts
+}
@@ -0,0 +1,285 @@
+use clap::ValueEnum;
+use std::{
+    fs::{File, Metadata},
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// How symbolic links below the document root are treated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SymlinkPolicy {
+    /// Follow every symlink, wherever it points
+    Follow,
+    /// Follow symlinks whose target stays inside the document root
+    #[default]
+    WithinRoot,
+    /// Follow symlinks owned by the same user as their target
+    OwnerMatch,
+    /// Never follow symlinks
+    Never,
+}
+
+fn denied(reason: &str) -> io::Error {
+    io::Error::new(ErrorKind::PermissionDenied, reason.to_string())
+}
+
+/// An open handle on the document root. Files are opened one path component
+/// at a time relative to the root's descriptor, applying the symlink policy
+/// to every link on the way, so the descriptor that is returned is exactly
+/// the file that passed the checks.
+#[derive(Clone, Debug)]
+pub(crate) struct SafeRoot {
+    path: PathBuf,
+    policy: SymlinkPolicy,
+    dir: Arc<File>,
+}
+
+impl SafeRoot {
+    /// `path` must already be canonical.
+    pub fn new(path: PathBuf, policy: SymlinkPolicy) -> io::Result<Self> {
+        let dir = File::open(&path)?;
+        if !dir.metadata()?.is_dir() {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "document root is not a directory"));
+        }
+
+        Ok(SafeRoot {
+            path,
+            policy,
+            dir: Arc::new(dir),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Open `target`, a path below the root, returning the handle and the
+    /// fully resolved path. Policy violations are reported as
+    /// [`ErrorKind::PermissionDenied`].
+    pub async fn open(&self, target: &Path) -> io::Result<(File, PathBuf)> {
+        let rel = target.strip_prefix(&self.path)
+            .map_err(|_| denied("path is outside of the document root"))?
+            .to_path_buf();
+        let this = self.clone();
+
+        tokio::task::spawn_blocking(move || this.open_blocking(&rel))
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    /// Metadata of `target`, reached the same way as by [`open`](Self::open),
+    /// so that what lies behind a link the policy refuses stays unknown.
+    pub async fn metadata(&self, target: &Path) -> io::Result<Metadata> {
+        let (file, _) = self.open(target).await?;
+
+        tokio::task::spawn_blocking(move || file.metadata())
+            .await
+            .map_err(io::Error::other)?
+    }
+}
+
+#[cfg(unix)]
+mod walk {
+    use super::{denied, SafeRoot, SymlinkPolicy};
+    use std::{
+        collections::VecDeque,
+        ffi::{CString, OsStr, OsString},
+        fs::File,
+        io::{self, ErrorKind},
+        os::unix::{
+            ffi::{OsStrExt, OsStringExt},
+            fs::MetadataExt,
+            io::{AsRawFd, FromRawFd, RawFd},
+        },
+        path::{Component, Path, PathBuf},
+        sync::Arc,
+    };
+
+    /// Same limit Linux applies to nested symlinks during path resolution.
+    const MAX_SYMLINK_HOPS: usize = 40;
+
+    enum Step {
+        Name(OsString),
+        Parent,
+        /// Check that the object just resolved is owned by this user
+        CheckOwner(u32),
+    }
+
+    struct Dir {
+        file: Arc<File>,
+        path: PathBuf,
+    }
+
+    fn c_name(name: &OsStr) -> io::Result<CString> {
+        CString::new(name.as_bytes()).map_err(|_| io::Error::new(ErrorKind::InvalidInput, "path contains NUL"))
+    }
+
+    fn open_at(dir: RawFd, name: &OsStr) -> io::Result<File> {
+        let name = c_name(name)?;
+        let flags = libc::O_RDONLY | libc::O_NOFOLLOW | libc::O_CLOEXEC | libc::O_NOCTTY | libc::O_NONBLOCK;
+        let fd = unsafe { libc::openat(dir, name.as_ptr(), flags) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    fn lstat_at(dir: RawFd, name: &OsStr) -> io::Result<libc::stat> {
+        let name = c_name(name)?;
+        let mut st: libc::stat = unsafe { std::mem::zeroed() };
+        if unsafe { libc::fstatat(dir, name.as_ptr(), &mut st, libc::AT_SYMLINK_NOFOLLOW) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(st)
+    }
+
+    fn read_link_at(dir: RawFd, name: &OsStr) -> io::Result<PathBuf> {
+        let name = c_name(name)?;
+        let mut buf = vec![0u8; libc::PATH_MAX as usize];
+        let len = unsafe { libc::readlinkat(dir, name.as_ptr(), buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.truncate(len as usize);
+
+        Ok(PathBuf::from(OsString::from_vec(buf)))
+    }
+
+    fn open_dir(path: &Path) -> io::Result<Dir> {
+        Ok(Dir {
+            file: Arc::new(File::open(path)?),
+            path: path.to_path_buf(),
+        })
+    }
+
+    fn steps_for(path: &Path) -> VecDeque<Step> {
+        path.components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(Step::Name(name.to_os_string())),
+                Component::ParentDir => Some(Step::Parent),
+                _ => None,
+            })
+            .collect()
+    }
+
+    impl SafeRoot {
+        pub(super) fn open_blocking(&self, rel: &Path) -> io::Result<(File, PathBuf)> {
+            let mut chain = vec![Dir {
+                file: Arc::clone(&self.dir),
+                path: self.path.clone(),
+            }];
+            let mut steps = steps_for(rel);
+            let mut opened_file: Option<(File, PathBuf)> = None;
+            let mut hops = 0;
+
+            while let Some(step) = steps.pop_front() {
+                if opened_file.is_some() && !matches!(step, Step::CheckOwner(_)) {
+                    return Err(io::Error::new(ErrorKind::NotFound, "not a directory"));
+                }
+
+                match step {
+                    Step::Parent if chain.len() > 1 => {
+                        chain.pop();
+                    },
+                    Step::Parent => {
+                        if self.policy == SymlinkPolicy::WithinRoot {
+                            return Err(denied("symlink points outside of the document root"));
+                        }
+                        if let Some(parent) = chain[0].path.parent() {
+                            chain[0] = open_dir(parent)?;
+                        }
+                    },
+                    Step::CheckOwner(uid) => {
+                        let owner = match &opened_file {
+                            Some((file, _)) => file.metadata()?.uid(),
+                            None => chain.last().unwrap().file.metadata()?.uid(),
+                        };
+                        if owner != uid {
+                            return Err(denied("symlink owner does not match its target"));
+                        }
+                    },
+                    Step::Name(name) => {
+                        let dir = chain.last().unwrap();
+                        let dir_fd = dir.file.as_raw_fd();
+                        let path = dir.path.join(&name);
+
+                        let err = match open_at(dir_fd, &name) {
+                            Ok(file) => {
+                                let meta = file.metadata()?;
+                                if meta.is_dir() {
+                                    chain.push(Dir { file: Arc::new(file), path });
+                                } else if meta.is_file() {
+                                    opened_file = Some((file, path));
+                                } else {
+                                    return Err(io::Error::new(ErrorKind::NotFound, "not a regular file"));
+                                }
+                                continue;
+                            },
+                            Err(e) => e,
+                        };
+
+                        let st = match lstat_at(dir_fd, &name) {
+                            Ok(st) if st.st_mode & libc::S_IFMT == libc::S_IFLNK => st,
+                            _ => return Err(err),
+                        };
+
+                        hops += 1;
+                        if hops > MAX_SYMLINK_HOPS {
+                            return Err(io::Error::other("too many levels of symbolic links"));
+                        }
+                        if self.policy == SymlinkPolicy::Never {
+                            return Err(denied("symlinks are not followed"));
+                        }
+
+                        let target = read_link_at(dir_fd, &name)?;
+                        let mut link_steps = if target.is_absolute() {
+                            if self.policy == SymlinkPolicy::WithinRoot {
+                                let rel = target.strip_prefix(&self.path)
+                                    .map_err(|_| denied("symlink points outside of the document root"))?;
+                                chain.truncate(1);
+                                steps_for(rel)
+                            } else {
+                                chain = vec![open_dir(Path::new("/"))?];
+                                steps_for(&target)
+                            }
+                        } else {
+                            steps_for(&target)
+                        };
+                        if self.policy == SymlinkPolicy::OwnerMatch {
+                            link_steps.push_back(Step::CheckOwner(st.st_uid));
+                        }
+
+                        while let Some(step) = link_steps.pop_back() {
+                            steps.push_front(step);
+                        }
+                    },
+                }
+            }
+
+            match opened_file {
+                Some(opened) => Ok(opened),
+                None => {
+                    let dir = chain.pop().unwrap();
+                    Ok((dir.file.try_clone()?, dir.path))
+                },
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+impl SafeRoot {
+    /// Without descriptor-relative opening the check falls back to
+    /// canonicalizing first, which leaves a window between check and open.
+    fn open_blocking(&self, rel: &Path) -> io::Result<(File, PathBuf)> {
+        let path = std::fs::canonicalize(self.path.join(rel))?;
+        if self.policy != SymlinkPolicy::Follow && !path.starts_with(&self.path) {
+            return Err(denied("path is outside of the document root"));
+        }
+
+        Ok((File::open(&path)?, path))
+    }
+}
@@ -0,0 +1,176 @@
+/// Number of leading bytes the WHATWG sniffing rules look at.
+pub(crate) const SNIFF_LEN: usize = 1445;
+
+/// Number of leading bytes checked when choosing the charset of text.
+pub(crate) const CHARSET_SNIFF_LEN: usize = 8 * 1024;
+
+/// Tags that identify HTML when they open a resource (after whitespace) and
+/// are followed by a space or `>`. Matched case-insensitively.
+const HTML_TAGS: [&[u8]; 17] = [
+    b"<!DOCTYPE HTML", b"<HTML", b"<HEAD", b"<SCRIPT", b"<IFRAME", b"<H1",
+    b"<DIV", b"<FONT", b"<TABLE", b"<A", b"<STYLE", b"<TITLE", b"<B",
+    b"<BODY", b"<BR", b"<P", b"<!--",
+];
+
+/// Signatures from the WHATWG image, audio/video and archive tables. `?`
+/// matches any byte.
+const SIGNATURES: [(&[u8], &str); 20] = [
+    (b"%PDF-", "application/pdf"),
+    (b"%!PS-Adobe-", "application/postscript"),
+    (b"\xFE\xFF", "text/plain"),
+    (b"\xFF\xFE", "text/plain"),
+    (b"\xEF\xBB\xBF", "text/plain"),
+    (b"\x00\x00\x01\x00", "image/x-icon"),
+    (b"\x00\x00\x02\x00", "image/x-icon"),
+    (b"BM", "image/bmp"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"RIFF????WEBPVP", "image/webp"),
+    (b"\x89PNG\r\n\x1A\n", "image/png"),
+    (b"\xFF\xD8\xFF", "image/jpeg"),
+    (b".snd", "audio/basic"),
+    (b"FORM????AIFF", "audio/aiff"),
+    (b"ID3", "audio/mpeg"),
+    (b"OggS\x00", "application/ogg"),
+    (b"MThd\x00\x00\x00\x06", "audio/midi"),
+    (b"RIFF????AVI ", "video/avi"),
+    (b"RIFF????WAVE", "audio/wave"),
+];
+
+/// Archive signatures, checked after the media types.
+const ARCHIVE_SIGNATURES: [(&[u8], &str); 3] = [
+    (b"\x1F\x8B\x08", "application/x-gzip"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"Rar!\x1A\x07\x00", "application/x-rar-compressed"),
+];
+
+fn matches_signature(data: &[u8], pattern: &[u8]) -> bool {
+    data.len() >= pattern.len()
+        && pattern.iter().zip(data).all(|(p, d)| *p == b'?' || p == d)
+}
+
+fn is_whitespace(b: u8) -> bool {
+    matches!(b, b'\t' | b'\n' | 0x0C | b'\r' | b' ')
+}
+
+fn is_html(data: &[u8]) -> bool {
+    let start = data.iter().position(|b| !is_whitespace(*b)).unwrap_or(data.len());
+    let data = &data[start..];
+
+    HTML_TAGS.iter().any(|tag| {
+        data.len() > tag.len()
+            && data[..tag.len()].eq_ignore_ascii_case(tag)
+            && matches!(data[tag.len()], b' ' | b'>')
+    })
+}
+
+fn is_xml(data: &[u8]) -> bool {
+    let start = data.iter().position(|b| !is_whitespace(*b)).unwrap_or(data.len());
+    data[start..].starts_with(b"<?xml")
+}
+
+/// The "matches the signature for MP4" algorithm.
+fn is_mp4(data: &[u8]) -> bool {
+    if data.len() < 12 {
+        return false;
+    }
+    let box_size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    if data.len() < box_size || !box_size.is_multiple_of(4) || &data[4..8] != b"ftyp" {
+        return false;
+    }
+    if &data[8..11] == b"mp4" {
+        return true;
+    }
+
+    (16..box_size).step_by(4).any(|i| i + 3 <= data.len() && &data[i..i + 3] == b"mp4")
+}
+
+/// The "matches the signature for WebM" algorithm: an EBML header whose
+/// DocType element says `webm`.
+fn is_webm(data: &[u8]) -> bool {
+    if !data.starts_with(b"\x1A\x45\xDF\xA3") {
+        return false;
+    }
+
+    let mut i = 4;
+    while i + 1 < data.len() && i < 38 {
+        if data[i] == 0x42 && data[i + 1] == 0x82 {
+            let size_len = data.get(i + 2).map(|b| b.leading_zeros() as usize + 1).unwrap_or(9);
+            let doc_type = i + 2 + size_len;
+            return size_len <= 8 && data.get(doc_type..doc_type + 4) == Some(b"webm".as_slice());
+        }
+        i += 1;
+    }
+
+    false
+}
+
+fn has_binary_bytes(data: &[u8]) -> bool {
+    data.iter().any(|b| matches!(b, 0x00..=0x08 | 0x0B | 0x0E..=0x1A | 0x1C..=0x1F))
+}
+
+/// Identify the type of a resource from its first bytes. Anything that is
+/// not recognised is `text/plain` if it contains no binary bytes and
+/// `application/octet-stream` otherwise.
+pub(crate) fn sniff(head: &[u8]) -> &'static str {
+    let data = &head[..head.len().min(SNIFF_LEN)];
+
+    if is_html(data) {
+        return "text/html";
+    }
+    if is_xml(data) {
+        return "text/xml";
+    }
+    if let Some((_, mime)) = SIGNATURES.iter().find(|(sig, _)| matches_signature(data, sig)) {
+        return mime;
+    }
+    if is_mp4(data) {
+        return "video/mp4";
+    }
+    if is_webm(data) {
+        return "video/webm";
+    }
+    if let Some((_, mime)) = ARCHIVE_SIGNATURES.iter().find(|(sig, _)| matches_signature(data, sig)) {
+        return mime;
+    }
+
+    if has_binary_bytes(data) {
+        "application/octet-stream"
+    } else {
+        "text/plain"
+    }
+}
+
+/// The charset of text starting with `head`: from its byte order mark, or
+/// UTF-8 if it validates as such. `head` may be cut off mid-character.
+fn charset(head: &[u8]) -> Option<&'static str> {
+    if head.starts_with(b"\xEF\xBB\xBF") {
+        return Some("utf-8");
+    }
+    if head.starts_with(b"\xFE\xFF") {
+        return Some("utf-16be");
+    }
+    if head.starts_with(b"\xFF\xFE") {
+        return Some("utf-16le");
+    }
+
+    match std::str::from_utf8(head) {
+        Ok(_) => Some("utf-8"),
+        Err(e) if e.error_len().is_none() && head.len() >= CHARSET_SNIFF_LEN => Some("utf-8"),
+        Err(_) => None,
+    }
+}
+
+/// Add a charset parameter to text types when one can be determined from
+/// `head`. Other types, and types that already carry parameters, are
+/// returned unchanged.
+pub(crate) fn with_charset(mime: &str, head: &[u8]) -> String {
+    if !mime.starts_with("text/") || mime.contains(';') {
+        return mime.to_string();
+    }
+
+    match charset(head) {
+        Some(charset) => format!("{}; charset={}", mime, charset),
+        None => mime.to_string(),
+    }
+}
@@ -0,0 +1,368 @@
+use crate::{
+    access_policy,
+    conditional::{self, Precondition},
+    file_body::{full_body, ResponseBody},
+    file_resolver::ResolverOptions,
+    safe_open::SafeRoot,
+    sniff,
+    util,
+};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use hyper::{body::Bytes, http::request::Parts, Response, StatusCode};
+use serde_json::Value;
+use std::{
+    env,
+    future::Future,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+};
+use tokio::{fs, io::AsyncReadExt};
+use tracing::{error, trace};
+
+/// Deepest chain of nested includes.
+const MAX_DEPTH: usize = 16;
+
+/// Largest document, included or not, that is processed.
+const MAX_SOURCE_LEN: u64 = 4 * 1024 * 1024;
+
+/// Size at which a page stops taking in includes, so a few documents that
+/// include each other repeatedly cannot grow it without bound.
+const MAX_OUTPUT_LEN: usize = 16 * 1024 * 1024;
+
+/// Put in place of directives that fail, as Apache does.
+const ERROR_MARKER: &str = "[an error occurred while processing this directive]";
+
+/// Which documents are assembled from server-side include directives, and
+/// what `echo` can print. Matching documents are scanned for:
+///
+/// - `<!--#include virtual="/partials/header.html" -->`: a URL path below the
+///   resolver's root, relative paths starting from the including document.
+///   `file="..."` takes relative paths only. Included documents are opened
+///   under the same containment, symlink and access rules as requests, and
+///   are processed for directives themselves.
+/// - `<!--#echo var="NAME" -->`: HTML-escaped unless `encoding="none"`, or
+///   `default="..."` if the variable is not set. Variables are
+///   `DOCUMENT_URI`, `DOCUMENT_NAME`, `QUERY_STRING` and `REQUEST_METHOD`,
+///   `query.KEY` for query parameters, `data.KEY.KEY` for the data file and
+///   `env.NAME` for the environment variables that were exposed.
+#[derive(Clone, Debug)]
+pub struct ServerIncludes {
+    /// Patterns without a `/`, matched against the file name
+    names: GlobSet,
+    /// Patterns matched against the whole path below the root
+    paths: GlobSet,
+    data: Arc<Value>,
+    env: Vec<String>,
+}
+
+impl ServerIncludes {
+    /// Process documents matching one of `patterns`, e.g. `*.shtml` or
+    /// `pages/**/*.html`.
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<Self, globset::Error> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        for pattern in patterns {
+            let pattern = pattern.as_ref();
+            if pattern.contains('/') {
+                paths.add(Glob::new(pattern.trim_start_matches('/'))?);
+            } else {
+                names.add(Glob::new(pattern)?);
+            }
+        }
+
+        Ok(ServerIncludes {
+            names: names.build()?,
+            paths: paths.build()?,
+            data: Arc::new(Value::Null),
+            env: Vec::new(),
+        })
+    }
+
+    /// Let `echo` print values from `data` as `data.*`.
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Arc::new(data);
+        self
+    }
+
+    /// Let `echo` print the environment variables `names` as `env.*`; the
+    /// rest of the environment stays hidden.
+    pub fn with_env<S: AsRef<str>>(mut self, names: &[S]) -> Self {
+        self.env.extend(names.iter().map(|name| name.as_ref().to_string()));
+        self
+    }
+
+    /// Whether the file at `path`, below `root`, is processed.
+    pub(crate) fn applies_to(&self, path: &Path, root: &Path) -> bool {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if self.names.is_match(name.as_ref()) {
+            return true;
+        }
+
+        match path.strip_prefix(root) {
+            Ok(rel) => {
+                let rel: Vec<_> = rel.components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect();
+                self.paths.is_match(rel.join("/"))
+            },
+            Err(_) => false,
+        }
+    }
+
+    fn data_value(&self, key: &str) -> Option<String> {
+        let value = key.split('.').try_fold(&*self.data, |value, key| match value {
+            Value::Object(map) => map.get(key),
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => None,
+        })?;
+
+        match value {
+            Value::Null => None,
+            Value::String(s) => Some(s.clone()),
+            value => Some(value.to_string()),
+        }
+    }
+}
+
+/// Find `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Split the inside of a directive, e.g. `include virtual="/a.html" `, into
+/// its command and attributes.
+fn parse_directive(text: &str) -> Option<(&str, Vec<(&str, &str)>)> {
+    let text = text.trim();
+    let (command, mut rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+
+    let mut attributes = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+
+        let (name, value) = rest.split_once('=')?;
+        let value = value.trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let (value, after) = value[1..].split_once(quote)?;
+        attributes.push((name.trim(), value));
+        rest = after;
+    }
+
+    Some((command, attributes))
+}
+
+/// Read a whole document to process it. `None` if it is too large.
+async fn read_document(file: &mut fs::File, options: &ResolverOptions) -> io::Result<Option<Vec<u8>>> {
+    let meta = file.metadata().await?;
+    if !meta.is_file() {
+        return Err(io::Error::new(ErrorKind::NotFound, "not a file"));
+    }
+    if meta.len() > MAX_SOURCE_LEN {
+        return Ok(None);
+    }
+
+    let mut source = Vec::with_capacity(meta.len() as usize);
+    file.read_to_end(&mut source).await?;
+    if options.access.refuses_private_keys() && access_policy::looks_like_private_key(&source) {
+        return Err(io::Error::new(ErrorKind::NotFound, "file looks like a private key"));
+    }
+
+    Ok(Some(source))
+}
+
+/// A page being assembled for one request.
+struct Page<'a> {
+    root: &'a SafeRoot,
+    options: &'a ResolverOptions,
+    includes: &'a ServerIncludes,
+    req: &'a Parts,
+    /// Documents being processed, outermost first
+    stack: Vec<PathBuf>,
+    out: Vec<u8>,
+}
+
+impl Page<'_> {
+    /// Copy `source`, found at `doc_path` below the root, to the page with
+    /// its directives carried out.
+    fn process<'b>(&'b mut self, source: &'b [u8], doc_path: &'b str) -> Pin<Box<dyn Future<Output = ()> + Send + 'b>> {
+        Box::pin(async move {
+            let mut rest = source;
+            while let Some(start) = find(rest, b"<!--#") {
+                self.out.extend_from_slice(&rest[..start]);
+                let Some(len) = find(&rest[start..], b"-->") else {
+                    rest = &rest[start..];
+                    break;
+                };
+
+                let text = String::from_utf8_lossy(&rest[start + 5..start + len]);
+                rest = &rest[start + len + 3..];
+                if let Err(e) = self.run(&text, doc_path).await {
+                    error!("Failed to process <!--#{}--> in {}: {}", text, doc_path, e);
+                    self.out.extend_from_slice(ERROR_MARKER.as_bytes());
+                }
+            }
+            self.out.extend_from_slice(rest);
+        })
+    }
+
+    async fn run(&mut self, text: &str, doc_path: &str) -> io::Result<()> {
+        let invalid = |msg: &str| io::Error::new(ErrorKind::InvalidInput, msg.to_string());
+        let (command, attributes) = parse_directive(text).ok_or_else(|| invalid("malformed directive"))?;
+        let attribute = |name: &str| attributes.iter().find(|(n, _)| *n == name).map(|(_, v)| *v);
+
+        match command {
+            "include" => {
+                let dir = &doc_path[..doc_path.rfind('/').map_or(0, |i| i + 1)];
+                let target = match (attribute("virtual"), attribute("file")) {
+                    (Some(url), None) if url.starts_with('/') => url.to_string(),
+                    (Some(url), None) => format!("{}{}", dir, url),
+                    (None, Some(file)) if !file.starts_with('/') && !file.split('/').any(|s| s == "..") => {
+                        format!("{}{}", dir, file)
+                    },
+                    _ => return Err(invalid("expected a virtual or a relative file path")),
+                };
+                let target = util::normalize_request_path(&target, false)
+                    .map_err(|e| invalid(&format!("invalid include path: {:?}", e)))?;
+
+                self.include(&target).await
+            },
+            "echo" => {
+                let name = attribute("var").ok_or_else(|| invalid("missing var"))?;
+                let value = self.variable(name).or_else(|| attribute("default").map(String::from));
+                if value.is_none() {
+                    trace!("SSI variable {} is not set", name);
+                }
+
+                let value = value.unwrap_or_default();
+                let value = match attribute("encoding") {
+                    Some("none") => value,
+                    Some("entity") | None => util::html_escape(&value),
+                    Some(_) => return Err(invalid("unknown encoding")),
+                };
+                self.out.extend_from_slice(value.as_bytes());
+                Ok(())
+            },
+            _ => Err(invalid("unknown directive")),
+        }
+    }
+
+    /// Process the document at `url_path`, below the root, into the page.
+    async fn include(&mut self, url_path: &str) -> io::Result<()> {
+        if self.stack.len() >= MAX_DEPTH {
+            return Err(io::Error::new(ErrorKind::InvalidData, "includes nested too deeply"));
+        }
+        if self.out.len() >= MAX_OUTPUT_LEN {
+            return Err(io::Error::new(ErrorKind::InvalidData, "page too large"));
+        }
+        if !self.options.access.permits(url_path) {
+            return Err(io::Error::new(ErrorKind::NotFound, "refused by the access policy"));
+        }
+
+        let root = self.root;
+        let (file, resolved_path) = root.open(&root.path().join(&url_path[1..])).await?;
+        if !self.options.access.permits_path(&resolved_path, root.path()) {
+            return Err(io::Error::new(ErrorKind::NotFound, "refused by the access policy"));
+        }
+        if self.stack.contains(&resolved_path) {
+            return Err(io::Error::new(ErrorKind::InvalidData, "include cycle"));
+        }
+
+        let source = read_document(&mut file.into(), self.options).await?
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "document too large to include"))?;
+        self.stack.push(resolved_path);
+        self.process(&source, url_path).await;
+        self.stack.pop();
+
+        Ok(())
+    }
+
+    fn variable(&self, name: &str) -> Option<String> {
+        let query = self.req.uri.query().unwrap_or("");
+        if let Some(key) = name.strip_prefix("query.") {
+            return util::parse_query(query).remove(key);
+        }
+        if let Some(key) = name.strip_prefix("data.") {
+            return self.includes.data_value(key);
+        }
+        if let Some(key) = name.strip_prefix("env.") {
+            if !self.includes.env.iter().any(|exposed| exposed == key) {
+                return None;
+            }
+            return env::var(key).ok();
+        }
+
+        match name {
+            "DOCUMENT_URI" => util::normalize_request_path(self.req.uri.path(), self.options.allow_encoded_slashes).ok(),
+            "DOCUMENT_NAME" => self.stack.first()
+                .and_then(|path| path.file_name())
+                .map(|name| name.to_string_lossy().into_owned()),
+            "QUERY_STRING" => Some(query.to_string()),
+            "REQUEST_METHOD" => Some(self.req.method.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Respond with the document `file`, found at `path` and requested as
+/// `doc_path` below the root, assembled from its include directives. The
+/// page can change with any of its includes, so it is validated by a hash of
+/// the result rather than by the document alone. `None` if the document is
+/// too large to process, in which case it should be served as it is.
+pub(crate) async fn processed_response(
+    file: &mut fs::File,
+    path: &Path,
+    doc_path: &str,
+    root: &SafeRoot,
+    options: &ResolverOptions,
+    req: &Parts,
+) -> io::Result<Option<Response<ResponseBody>>> {
+    let includes = options.includes.as_ref()
+        .ok_or_else(|| io::Error::other("server-side includes are not enabled"))?;
+    let source = match read_document(file, options).await? {
+        Some(source) => source,
+        None => return Ok(None),
+    };
+
+    let mut page = Page {
+        root,
+        options,
+        includes,
+        req,
+        stack: vec![path.to_path_buf()],
+        out: Vec::with_capacity(source.len()),
+    };
+    page.process(&source, doc_path).await;
+    let out = page.out;
+
+    let head = &out[..out.len().min(sniff::CHARSET_SNIFF_LEN)];
+    // Unregistered extensions are sniffed: the page is markup either way
+    let mime = options.mime_types.content_type(path, head, true);
+    let validators = conditional::Validators {
+        etag: conditional::strong_etag_for(&out),
+        last_modified: None,
+    };
+
+    let mut builder = Response::builder()
+        .header("ETag", &validators.etag);
+    if let Some(directives) = options.cache.directives_for_path(path, root.path(), &mime) {
+        builder = builder.header("Cache-Control", directives);
+    }
+
+    let res = match conditional::evaluate(&req.headers, &req.method, &validators) {
+        Precondition::NotModified => builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(full_body(Bytes::new())),
+        Precondition::Failed => builder
+            .status(StatusCode::PRECONDITION_FAILED)
+            .body(full_body("Precondition failed")),
+        Precondition::Proceed => builder.status(200)
+            .header("Content-Type", mime)
+            .body(full_body(out)),
+    };
+
+    Ok(Some(res.unwrap()))
+}
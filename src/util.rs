@@ -25,6 +25,148 @@ pub fn parse_path_components(buf: &[u8]) -> (String, HashMap<String, String>) {
     (path, query)
 }
 
+/// Split a raw query string (without the leading `?`) into percent-decoded
+/// key/value pairs. Keys without a value map to an empty string.
+pub fn parse_query(query: &str) -> HashMap<String, String> {
+    let decode = |s: &str| {
+        let s = s.replace('+', " ");
+        match percent_decode(&s) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(_) => s,
+        }
+    };
+
+    query.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((k, v)) => (decode(k), decode(v)),
+            None => (decode(pair), String::new()),
+        })
+        .collect()
+}
+
+/// Reasons a request path is refused by [`normalize_request_path`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum PathError {
+    /// A `%` not followed by two hex digits
+    InvalidEncoding,
+    /// The decoded path is not valid UTF-8
+    InvalidUtf8,
+    /// The path contains a NUL byte, encoded or not
+    Nul,
+    /// The path contains an encoded `/` and those are not allowed
+    EncodedSlash,
+    /// The path does not start with `/`
+    NotAbsolute,
+}
+
+/// Decode `%XX` escapes in `input`.
+pub fn percent_decode(input: &str) -> Result<Vec<u8>, PathError> {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' {
+            let hex = bytes.get(idx + 1..idx + 3)
+                .filter(|h| h.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|h| std::str::from_utf8(h).ok())
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+                .ok_or(PathError::InvalidEncoding)?;
+            out.push(hex);
+            idx += 3;
+        } else {
+            out.push(bytes[idx]);
+            idx += 1;
+        }
+    }
+
+    Ok(out)
+}
+
+/// Percent-decode a request path and resolve `.` and `..` segments without
+/// touching the filesystem. The result always starts with `/`, never climbs
+/// above it, and keeps a trailing slash if the request had one. Encoded
+/// slashes are rejected unless `allow_encoded_slash` is set, in which case
+/// they separate segments like a literal `/`.
+pub fn normalize_request_path(raw: &str, allow_encoded_slash: bool) -> Result<String, PathError> {
+    if !raw.starts_with('/') {
+        return Err(PathError::NotAbsolute);
+    }
+
+    let mut segments: Vec<String> = Vec::new();
+    let raw_segments: Vec<&str> = raw[1..].split('/').collect();
+    let trailing_slash = raw_segments.len() > 1
+        && matches!(raw_segments.last(), Some(&"") | Some(&".") | Some(&".."));
+
+    for raw_segment in raw_segments {
+        let decoded = String::from_utf8(percent_decode(raw_segment)?)
+            .map_err(|_| PathError::InvalidUtf8)?;
+        if decoded.contains('\0') {
+            return Err(PathError::Nul);
+        }
+        if decoded.contains('/') && !allow_encoded_slash {
+            return Err(PathError::EncodedSlash);
+        }
+
+        for segment in decoded.split('/') {
+            match segment {
+                "" | "." => (),
+                ".." => {
+                    segments.pop();
+                },
+                _ => segments.push(segment.to_string()),
+            }
+        }
+    }
+
+    let mut path = format!("/{}", segments.join("/"));
+    if trailing_slash && !segments.is_empty() {
+        path.push('/');
+    }
+
+    Ok(path)
+}
+
+/// Percent-encode each segment of a decoded `/`-separated path.
+pub fn percent_encode_path(path: &str) -> String {
+    path.split('/')
+        .map(percent_encode_segment)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Percent-encode everything except RFC 3986 unreserved characters so the
+/// result can be used as a single path segment.
+pub fn percent_encode_segment(segment: &str) -> String {
+    let mut out = String::with_capacity(segment.len());
+    for b in segment.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+
+    out
+}
+
+/// Escape the characters that are significant in HTML text and attributes.
+pub fn html_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
 pub fn parse_locator(buf: &[u8], start: usize) -> (String, usize) {
     if start >= buf.len() {
         return ("".to_string(), start);
@@ -59,6 +201,7 @@ pub fn parse_version(buf: &[u8], start: usize) -> (String, usize) {
     }
 }
 
+#[allow(clippy::needless_borrow)]
 pub fn parse_headers(buf: &[u8], start: usize) -> HashMap<String, String> {
     let mut headers = HashMap::new();
     let mut sbuf = &buf[start..];
@@ -78,6 +221,7 @@ pub fn parse_headers(buf: &[u8], start: usize) -> HashMap<String, String> {
     headers
 }
 
+#[allow(clippy::manual_unwrap_or_default)]
 fn parse_header_name(buf: &[u8]) -> (String, &[u8]) {
     let mut idx = 0;
     loop {
@@ -103,6 +247,7 @@ fn parse_header_name(buf: &[u8]) -> (String, &[u8]) {
     (header_name, &buf[idx + 2..])
 }
 
+#[allow(clippy::manual_unwrap_or_default)]
 fn parse_header_value(buf: &[u8]) -> (String, &[u8]) {
     let mut idx = 0;
 
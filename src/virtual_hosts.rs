@@ -0,0 +1,120 @@
+use crate::{
+    error_pages::builtin_response,
+    file_body::ResponseBody,
+    mount_table::{log_unrouted, MountTable},
+};
+use hyper::{
+    body::Incoming,
+    service::Service,
+    Request, Response, StatusCode,
+};
+use std::{cmp::Reverse, future::Future, pin::Pin};
+
+/// The host name a request is addressed to, from the request target if it
+/// is in absolute form and from the `Host` header otherwise. The port and
+/// any trailing dot are dropped and the name is lowercased.
+fn request_host(req: &Request<Incoming>) -> Option<String> {
+    let host = match req.uri().host() {
+        Some(host) => host,
+        None => req.headers().get("Host")?.to_str().ok()?,
+    };
+
+    let name = if let Some(rest) = host.strip_prefix('[') {
+        // IPv6 literal, keep the brackets
+        &host[..rest.find(']')? + 2]
+    } else {
+        host.rsplit_once(':').map_or(host, |(name, _)| name)
+    };
+    let name = name.trim_end_matches('.');
+
+    (!name.is_empty()).then(|| name.to_ascii_lowercase())
+}
+
+#[derive(Clone)]
+enum HostPattern {
+    Exact(String),
+    /// `*.example.com`, stored as `.example.com`; matches any subdomain
+    Wildcard(String),
+}
+
+impl HostPattern {
+    fn parse(pattern: &str) -> Self {
+        let pattern = pattern.trim_end_matches('.').to_ascii_lowercase();
+        match pattern.strip_prefix('*') {
+            Some(suffix) => HostPattern::Wildcard(suffix.to_string()),
+            None => HostPattern::Exact(pattern),
+        }
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            HostPattern::Exact(name) => host == name,
+            HostPattern::Wildcard(suffix) => host.len() > suffix.len() && host.ends_with(suffix.as_str()),
+        }
+    }
+
+    /// Exact names are tried first, then wildcards with the longest suffix.
+    fn rank(&self) -> (bool, Reverse<usize>) {
+        match self {
+            HostPattern::Exact(name) => (false, Reverse(name.len())),
+            HostPattern::Wildcard(suffix) => (true, Reverse(suffix.len())),
+        }
+    }
+}
+
+/// Dispatches requests to a site, a [`MountTable`], chosen by the host name
+/// they are addressed to. Host patterns are either exact names such as
+/// `app.localhost` or wildcards such as `*.localhost`; requests for other
+/// hosts, or without a host, go to the default site if there is one.
+#[derive(Clone, Default)]
+pub struct VirtualHosts {
+    hosts: Vec<(HostPattern, MountTable)>,
+    default: Option<MountTable>,
+}
+
+impl VirtualHosts {
+    pub fn new() -> Self {
+        VirtualHosts::default()
+    }
+
+    /// Serve requests for hosts matching `pattern` from `site`.
+    pub fn add_host(&mut self, pattern: &str, site: MountTable) {
+        self.hosts.push((HostPattern::parse(pattern), site));
+        self.hosts.sort_by_key(|(pattern, _)| pattern.rank());
+    }
+
+    /// Serve requests for unknown hosts from `site`.
+    pub fn set_default(&mut self, site: MountTable) {
+        self.default = Some(site);
+    }
+
+    /// The site serving `host`, if one was added for it.
+    pub fn site(&self, host: &str) -> Option<&MountTable> {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.hosts.iter()
+            .find(|(pattern, _)| pattern.matches(&host))
+            .map(|(_, site)| site)
+    }
+}
+
+impl Service<Request<Incoming>> for VirtualHosts {
+    type Response = Response<ResponseBody>;
+    type Error = hyper::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn call(&mut self, req: Request<Incoming>) -> Self::Future {
+        let site = match request_host(&req) {
+            Some(host) => self.hosts.iter_mut()
+                .find(|(pattern, _)| pattern.matches(&host))
+                .map(|(_, site)| site),
+            None => None,
+        };
+        if let Some(site) = site.or(self.default.as_mut()) {
+            return site.call(req);
+        }
+
+        let res = builtin_response(StatusCode::NOT_FOUND);
+        log_unrouted(&req, &res);
+        Box::pin(async move { Ok(res) })
+    }
+}
@@ -0,0 +1,251 @@
+use hyper::{
+    body::{Body, Bytes, Frame, Incoming, SizeHint},
+    server::conn::http1,
+    service::Service,
+    Request, Response,
+};
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fs::File,
+    io::{self, IoSlice},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+};
+
+/// Files smaller than this are cheaper to stream through hyper than to set
+/// up `sendfile(2)` for.
+pub(crate) const DEFAULT_MIN_SIZE: u64 = 1024 * 1024;
+
+/// Largest span of a file a single [`SendfileBody`] frame stands for.
+const PLACEHOLDER_LEN: usize = 4 * 1024 * 1024;
+
+/// The bytes every [`SendfileBody`] frame points into. hyper queues body
+/// frames without copying them when the connection supports vectored writes,
+/// so a slice into this buffer reaches [`ZeroCopyStream`] unchanged and marks
+/// where the next queued file span goes.
+static PLACEHOLDER: [u8; PLACEHOLDER_LEN] = [0; PLACEHOLDER_LEN];
+
+fn is_placeholder(buf: &[u8]) -> bool {
+    let start = PLACEHOLDER.as_ptr() as usize;
+    let ptr = buf.as_ptr() as usize;
+    !buf.is_empty() && ptr >= start && ptr < start + PLACEHOLDER_LEN
+}
+
+/// A span of a file waiting to be sent.
+struct Segment {
+    file: Arc<File>,
+    offset: u64,
+    len: usize,
+}
+
+/// File spans of the responses on one connection, in the order their
+/// placeholder frames were handed to hyper. Requests carry it in their
+/// extensions when the connection can send files directly.
+#[derive(Clone, Default)]
+pub(crate) struct SendfileQueue(Arc<Mutex<VecDeque<Segment>>>);
+
+impl SendfileQueue {
+    fn is_pending(&self) -> bool {
+        !self.0.lock().unwrap().is_empty()
+    }
+}
+
+/// A body whose frames are placeholders for spans of a file, sent by
+/// [`ZeroCopyStream`] straight from the page cache to the socket.
+pub(crate) struct SendfileBody {
+    queue: SendfileQueue,
+    file: Arc<File>,
+    offset: u64,
+    remaining: u64,
+}
+
+impl SendfileBody {
+    pub fn new(queue: SendfileQueue, file: File, start: u64, len: u64) -> Self {
+        SendfileBody {
+            queue,
+            file: Arc::new(file),
+            offset: start,
+            remaining: len,
+        }
+    }
+}
+
+impl Body for SendfileBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        if this.remaining == 0 {
+            return Poll::Ready(None);
+        }
+
+        let len = this.remaining.min(PLACEHOLDER_LEN as u64) as usize;
+        this.queue.0.lock().unwrap().push_back(Segment {
+            file: Arc::clone(&this.file),
+            offset: this.offset,
+            len,
+        });
+        this.offset += len as u64;
+        this.remaining -= len as u64;
+
+        Poll::Ready(Some(Ok(Frame::data(Bytes::from_static(&PLACEHOLDER[..len])))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.remaining == 0
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining)
+    }
+}
+
+/// A connection that writes the file spans of large responses with
+/// `sendfile(2)` and passes everything else through to the socket. Built by
+/// [`serve_zero_copy`], which makes hyper hand over frames uncopied.
+pub struct ZeroCopyStream {
+    stream: TcpStream,
+    queue: SendfileQueue,
+}
+
+impl ZeroCopyStream {
+    #[cfg(target_os = "linux")]
+    fn poll_sendfile(&mut self, cx: &mut Context<'_>, want: usize) -> Poll<io::Result<usize>> {
+        use std::os::unix::io::AsRawFd;
+        use tokio::io::Interest;
+
+        let mut queue = self.queue.0.lock().unwrap();
+        let segment = match queue.front_mut() {
+            Some(segment) if segment.len == want => segment,
+            _ => return Poll::Ready(Err(io::Error::other("placeholder frame out of step with its file"))),
+        };
+
+        let socket = self.stream.as_raw_fd();
+        let sent = loop {
+            std::task::ready!(self.stream.poll_write_ready(cx))?;
+            let res = self.stream.try_io(Interest::WRITABLE, || {
+                let mut offset = segment.offset as libc::off_t;
+                let n = unsafe { libc::sendfile(socket, segment.file.as_raw_fd(), &mut offset, segment.len) };
+                if n < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(n as usize)
+                }
+            });
+            match res {
+                Ok(n) => break n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        };
+        if sent == 0 {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "file shrank while being served")));
+        }
+
+        segment.offset += sent as u64;
+        segment.len -= sent;
+        if segment.len == 0 {
+            queue.pop_front();
+        }
+
+        Poll::Ready(Ok(sent))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn poll_sendfile(&mut self, _cx: &mut Context<'_>, _want: usize) -> Poll<io::Result<usize>> {
+        Poll::Ready(Err(io::Error::new(io::ErrorKind::Unsupported, "sendfile is only used on Linux")))
+    }
+}
+
+impl AsyncRead for ZeroCopyStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ZeroCopyStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.poll_write_vectored(cx, &[IoSlice::new(buf)])
+    }
+
+    fn poll_write_vectored(self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        // Write the plain bytes up to the first placeholder, then the file
+        // span it stands for
+        let plain = bufs.iter().position(|buf| is_placeholder(buf)).unwrap_or(bufs.len());
+        if plain == bufs.len() && this.queue.is_pending() && bufs.iter().any(|buf| !buf.is_empty()) {
+            // hyper copied a placeholder frame instead of passing it through,
+            // so the client would get zeros in place of the file
+            return Poll::Ready(Err(io::Error::other("placeholder frame was copied; file spans need vectored writes")));
+        }
+        if plain == bufs.len() || bufs[..plain].iter().any(|buf| !buf.is_empty()) {
+            return Pin::new(&mut this.stream).poll_write_vectored(cx, &bufs[..plain]);
+        }
+
+        this.poll_sendfile(cx, bufs[plain].len())
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+/// Wraps a service so the requests it sees can have large files sent with
+/// `sendfile(2)` over the matching [`ZeroCopyStream`].
+#[derive(Clone)]
+pub struct ZeroCopy<S> {
+    inner: S,
+    queue: SendfileQueue,
+}
+
+impl<S: Service<Request<Incoming>>> Service<Request<Incoming>> for ZeroCopy<S> {
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&mut self, mut req: Request<Incoming>) -> Self::Future {
+        if cfg!(target_os = "linux") {
+            req.extensions_mut().insert(self.queue.clone());
+        }
+        self.inner.call(req)
+    }
+}
+
+/// Serve a plain-HTTP connection with `builder` so that
+/// [`FileResolver`](crate::responders::FileResolver)s behind `service` send
+/// large files without copying them through userspace. The builder is
+/// switched to vectored writes, which this depends on. Elsewhere than on
+/// Linux the connection is served as usual.
+pub fn serve_zero_copy<S, B>(mut builder: http1::Builder, stream: TcpStream, service: S) -> http1::Connection<ZeroCopyStream, ZeroCopy<S>>
+where
+    S: Service<Request<Incoming>, Response = Response<B>>,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+    B: Body + 'static,
+    B::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    let queue = SendfileQueue::default();
+    let stream = ZeroCopyStream { stream, queue: queue.clone() };
+
+    builder.writev(true).serve_connection(stream, ZeroCopy { inner: service, queue })
+}
@@ -0,0 +1,31 @@
+use qsrv::responders::CachePolicy;
+
+const IMMUTABLE: Option<&str> = Some("public, max-age=31536000, immutable");
+
+fn directives(rel_path: &str) -> Option<String> {
+    CachePolicy::default()
+        .directives(rel_path, "application/octet-stream")
+        .map(String::from)
+}
+
+#[test]
+fn fingerprinted_names_are_immutable() {
+    assert_eq!(directives("assets/app.3f9a1c07.js"), IMMUTABLE.map(String::from));
+    assert_eq!(directives("app.3f9a1c.js"), IMMUTABLE.map(String::from));
+    assert_eq!(directives("main-0c1a9f2e.css"), IMMUTABLE.map(String::from));
+    assert_eq!(directives("index-B4dE9fQa.js"), IMMUTABLE.map(String::from));
+}
+
+#[test]
+fn dated_and_numbered_names_are_not_immutable() {
+    assert_eq!(directives("report-20240101.pdf"), None);
+    assert_eq!(directives("backup-123456.zip"), None);
+    assert_eq!(directives("photo.12345678.jpg"), None);
+}
+
+#[test]
+fn short_or_wordlike_parts_are_not_fingerprints() {
+    assert_eq!(directives("app.3f9a1.js"), None);
+    assert_eq!(directives("jquery.min.js"), None);
+    assert_eq!(directives("notes-deadbeef.txt"), None);
+}
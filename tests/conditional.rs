@@ -0,0 +1,125 @@
+mod common;
+
+use common::{get, spawn_server, Reply, TempDir};
+use qsrv::responders::{FileResolver, ResolverOptions};
+use std::net::SocketAddr;
+
+const BODY: &[u8] = b"conditional requests\n";
+const LONG_AGO: &str = "Thu, 01 Jan 1970 00:00:01 GMT";
+const FAR_AHEAD: &str = "Fri, 01 Jan 2100 00:00:00 GMT";
+
+async fn serve(dir: &TempDir, strong_etags: bool) -> SocketAddr {
+    dir.write("page.txt", BODY);
+    let options = ResolverOptions {
+        strong_etags,
+        ..ResolverOptions::default()
+    };
+    spawn_server(FileResolver::with_options(dir.to_str(), options).unwrap(), false).await
+}
+
+async fn page(addr: SocketAddr, headers: &[(&str, &str)]) -> Reply {
+    get(addr, "/page.txt", headers).await
+}
+
+async fn etag(addr: SocketAddr) -> String {
+    page(addr, &[]).await.header("ETag").unwrap().to_string()
+}
+
+#[tokio::test]
+async fn any_tag_matches_an_existing_file() {
+    let dir = TempDir::new("conditional-star");
+    let addr = serve(&dir, false).await;
+
+    assert_eq!(page(addr, &[("If-Match", "*")]).await.status, 200);
+    assert_eq!(page(addr, &[("If-None-Match", "*")]).await.status, 304);
+    assert_eq!(page(addr, &[("If-None-Match", "\"other\", *")]).await.status, 304);
+}
+
+#[tokio::test]
+async fn if_match_compares_strongly() {
+    let dir = TempDir::new("conditional-if-match");
+    let addr = serve(&dir, true).await;
+    let tag = etag(addr).await;
+    assert!(!tag.starts_with("W/"));
+
+    assert_eq!(page(addr, &[("If-Match", &tag)]).await.status, 200);
+    assert_eq!(page(addr, &[("If-Match", &format!("\"other\", {}", tag))]).await.status, 200);
+    assert_eq!(page(addr, &[("If-Match", "\"other\"")]).await.status, 412);
+    assert_eq!(page(addr, &[("If-Match", &format!("W/{}", tag))]).await.status, 412);
+
+    // A weak tag never matches strongly, not even itself
+    let dir = TempDir::new("conditional-if-match-weak");
+    let addr = serve(&dir, false).await;
+    let tag = etag(addr).await;
+    assert!(tag.starts_with("W/"));
+    assert_eq!(page(addr, &[("If-Match", &tag)]).await.status, 412);
+}
+
+#[tokio::test]
+async fn if_none_match_compares_weakly() {
+    let dir = TempDir::new("conditional-if-none-match");
+    let addr = serve(&dir, false).await;
+    let tag = etag(addr).await;
+    let opaque = tag.strip_prefix("W/").unwrap();
+
+    let reply = page(addr, &[("If-None-Match", &tag)]).await;
+    assert_eq!(reply.status, 304);
+    assert!(reply.body.is_empty());
+    assert_eq!(page(addr, &[("If-None-Match", opaque)]).await.status, 304);
+    assert_eq!(page(addr, &[("If-None-Match", "W/\"other\"")]).await.status, 200);
+}
+
+#[tokio::test]
+async fn if_match_takes_precedence_over_if_unmodified_since() {
+    let dir = TempDir::new("conditional-order");
+    let addr = serve(&dir, true).await;
+    let tag = etag(addr).await;
+
+    assert_eq!(page(addr, &[("If-Unmodified-Since", LONG_AGO)]).await.status, 412);
+    assert_eq!(page(addr, &[("If-Unmodified-Since", FAR_AHEAD)]).await.status, 200);
+
+    // If-Unmodified-Since is ignored once If-Match is present
+    assert_eq!(page(addr, &[("If-Match", &tag), ("If-Unmodified-Since", LONG_AGO)]).await.status, 200);
+    assert_eq!(page(addr, &[("If-Match", "\"other\""), ("If-Unmodified-Since", FAR_AHEAD)]).await.status, 412);
+
+    // Likewise If-Modified-Since once If-None-Match is present
+    assert_eq!(page(addr, &[("If-Modified-Since", FAR_AHEAD)]).await.status, 304);
+    assert_eq!(page(addr, &[("If-None-Match", "\"other\""), ("If-Modified-Since", FAR_AHEAD)]).await.status, 200);
+}
+
+#[tokio::test]
+async fn if_range_needs_a_strong_match() {
+    let dir = TempDir::new("conditional-if-range");
+    let addr = serve(&dir, true).await;
+    let tag = etag(addr).await;
+
+    let reply = page(addr, &[("Range", "bytes=0-3"), ("If-Range", &tag)]).await;
+    assert_eq!(reply.status, 206);
+    assert_eq!(reply.body, BODY[..4]);
+
+    for if_range in [format!("W/{}", tag), "\"other\"".to_string()] {
+        let reply = page(addr, &[("Range", "bytes=0-3"), ("If-Range", &if_range)]).await;
+        assert_eq!(reply.status, 200, "{}", if_range);
+        assert_eq!(reply.body, BODY, "{}", if_range);
+    }
+}
+
+#[tokio::test]
+async fn if_range_with_a_weak_tag_sends_the_whole_file() {
+    let dir = TempDir::new("conditional-if-range-weak");
+    let addr = serve(&dir, false).await;
+    let reply = page(addr, &[]).await;
+    let tag = reply.header("ETag").unwrap().to_string();
+    let last_modified = reply.header("Last-Modified").unwrap().to_string();
+
+    let reply = page(addr, &[("Range", "bytes=0-3"), ("If-Range", &tag)]).await;
+    assert_eq!(reply.status, 200);
+    assert_eq!(reply.header("Content-Range"), None);
+    assert_eq!(reply.body, BODY);
+
+    // The modification date still works as a validator
+    let reply = page(addr, &[("Range", "bytes=0-3"), ("If-Range", &last_modified)]).await;
+    assert_eq!(reply.status, 206);
+    assert_eq!(reply.body, BODY[..4]);
+    assert_eq!(page(addr, &[("Range", "bytes=0-3"), ("If-Range", LONG_AGO)]).await.status, 200);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::{get, spawn_server, TempDir};
+use qsrv::responders::{FileResolver, ResolverOptions};
+use std::net::SocketAddr;
+
+const BROWSER: (&str, &str) = ("Accept", "text/html,application/xhtml+xml;q=0.9,*/*;q=0.8");
+
+async fn serve(dir: &TempDir) -> SocketAddr {
+    let options = ResolverOptions {
+        markdown: true,
+        ..ResolverOptions::default()
+    };
+    spawn_server(FileResolver::with_options(dir.to_str(), options).unwrap(), false).await
+}
+
+async fn rendered(addr: SocketAddr, path: &str) -> String {
+    let reply = get(addr, path, &[BROWSER]).await;
+    assert_eq!(reply.status, 200);
+    assert_eq!(reply.header("Content-Type"), Some("text/html; charset=utf-8"));
+
+    String::from_utf8(reply.body).unwrap()
+}
+
+#[tokio::test]
+async fn documents_are_rendered_for_browsers() {
+    let dir = TempDir::new("markdown-render");
+    let source = "# Notes & more\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n- [x] done\n\n```rust\nfn main() {}\n```\n";
+    dir.write("notes.md", source);
+    let addr = serve(&dir).await;
+
+    let html = rendered(addr, "/notes.md").await;
+    assert!(html.contains("<title>Notes &amp; more</title>"));
+    assert!(html.contains("<h1>Notes &amp; more</h1>"));
+    assert!(html.contains("<table>"));
+    assert!(html.contains("checked=\"\""));
+    assert!(html.contains("<code class=\"language-rust\">"));
+
+    let reply = get(addr, "/notes.md?raw", &[BROWSER]).await;
+    assert_eq!(reply.body, source.as_bytes());
+    assert_eq!(get(addr, "/notes.md", &[]).await.body, source.as_bytes());
+}
+
+#[tokio::test]
+async fn raw_html_is_shown_as_text() {
+    let dir = TempDir::new("markdown-html");
+    dir.write("page.md", "# Page\n\n<script>alert(1)</script>\n\nSome <img src=x onerror=alert(2)> text\n\n<div onclick=\"alert(3)\">\nblock\n</div>\n");
+    let addr = serve(&dir).await;
+
+    let html = rendered(addr, "/page.md").await;
+    assert!(!html.contains("<script>"));
+    assert!(!html.contains("<img"));
+    assert!(!html.contains("<div"));
+    assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+    assert!(html.contains("Some &lt;img src=x onerror=alert(2)&gt; text"));
+    assert!(html.contains("&lt;div onclick=\"alert(3)\"&gt;"));
+}
+
+#[tokio::test]
+async fn script_links_lose_their_target() {
+    let dir = TempDir::new("markdown-links");
+    dir.write("links.md", "[a](javascript:alert(1)) [b](JavaScript:alert(2)) [c](java%0ascript:x) \
+        [d](<java\tscript:alert(3)>) [e](data:text/html,x) [f](https://example.com/) [g](other.md)\n\n![img](logo.png)\n");
+    let addr = serve(&dir).await;
+
+    let html = rendered(addr, "/links.md").await;
+    assert!(!html.to_ascii_lowercase().contains("javascript:"));
+    assert!(!html.contains("data:"));
+    assert!(html.contains("<a href=\"#\">a</a>"));
+    assert!(html.contains("<a href=\"#\">b</a>"));
+    assert!(html.contains("<a href=\"#\">e</a>"));
+    assert!(html.contains("<a href=\"https://example.com/\">f</a>"));
+    assert!(html.contains("<a href=\"other.md\">g</a>"));
+    assert!(html.contains("<img src=\"logo.png\" alt=\"img\" />"));
+}
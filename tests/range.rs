@@ -0,0 +1,149 @@
+mod common;
+
+use common::{get, spawn_server, Reply, TempDir};
+use qsrv::responders::{FileResolver, ResolverOptions};
+use std::net::SocketAddr;
+
+const FILE_SIZE: usize = 100;
+
+fn contents() -> Vec<u8> {
+    (0..FILE_SIZE).map(|i| b'a' + (i % 26) as u8).collect()
+}
+
+async fn serve(dir: &TempDir) -> SocketAddr {
+    dir.write("file.txt", contents());
+    spawn_server(FileResolver::with_options(dir.to_str(), ResolverOptions::default()).unwrap(), false).await
+}
+
+async fn get_range(addr: SocketAddr, range: &str) -> Reply {
+    get(addr, "/file.txt", &[("Range", range)]).await
+}
+
+/// The `(first, last)` positions and bytes of each part of a
+/// `multipart/byteranges` body, in the order they were sent.
+fn parts(reply: &Reply) -> Vec<(usize, usize, Vec<u8>)> {
+    let body = String::from_utf8(reply.body.clone()).unwrap();
+    body.split("Content-Range: bytes ")
+        .skip(1)
+        .map(|part| {
+            let (range, rest) = part.split_once('/').unwrap();
+            let (first, last) = range.split_once('-').unwrap();
+            let (first, last): (usize, usize) = (first.parse().unwrap(), last.parse().unwrap());
+            let data = rest.split_once("\r\n\r\n").unwrap().1.as_bytes()[..last - first + 1].to_vec();
+            (first, last, data)
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn suffix_ranges_select_the_end() {
+    let dir = TempDir::new("range-suffix");
+    let addr = serve(&dir).await;
+    let data = contents();
+
+    let reply = get_range(addr, "bytes=-10").await;
+    assert_eq!(reply.status, 206);
+    assert_eq!(reply.header("Content-Range"), Some("bytes 90-99/100"));
+    assert_eq!(reply.body, data[90..]);
+
+    // Longer than the file: the whole file
+    let reply = get_range(addr, "bytes=-500").await;
+    assert_eq!(reply.status, 206);
+    assert_eq!(reply.header("Content-Range"), Some("bytes 0-99/100"));
+    assert_eq!(reply.body, data);
+
+    assert_eq!(get_range(addr, "bytes=-0").await.status, 416);
+}
+
+#[tokio::test]
+async fn open_ended_ranges_run_to_the_end() {
+    let dir = TempDir::new("range-open");
+    let addr = serve(&dir).await;
+    let data = contents();
+
+    let reply = get_range(addr, "bytes=95-").await;
+    assert_eq!(reply.status, 206);
+    assert_eq!(reply.header("Content-Range"), Some("bytes 95-99/100"));
+    assert_eq!(reply.body, data[95..]);
+
+    let reply = get_range(addr, "bytes=0-").await;
+    assert_eq!(reply.status, 206);
+    assert_eq!(reply.header("Content-Range"), Some("bytes 0-99/100"));
+    assert_eq!(reply.body, data);
+
+    // A last position past the end is cut back to the end
+    let reply = get_range(addr, "bytes=98-1000").await;
+    assert_eq!(reply.header("Content-Range"), Some("bytes 98-99/100"));
+    assert_eq!(reply.body, data[98..]);
+}
+
+#[tokio::test]
+async fn multipart_ranges_keep_the_requested_order() {
+    let dir = TempDir::new("range-multipart");
+    let addr = serve(&dir).await;
+    let data = contents();
+
+    let reply = get_range(addr, "bytes=50-59, 10-19,15-24").await;
+    assert_eq!(reply.status, 206);
+    assert!(reply.header("Content-Type").unwrap().starts_with("multipart/byteranges; boundary="));
+    assert_eq!(reply.header("Content-Range"), None);
+
+    let parts = parts(&reply);
+    let spans: Vec<_> = parts.iter().map(|(first, last, _)| (*first, *last)).collect();
+    assert_eq!(spans, [(50, 59), (10, 19), (15, 24)]);
+    for (first, last, part) in parts {
+        assert_eq!(part, data[first..=last], "bytes {}-{}", first, last);
+    }
+
+    // Unsatisfiable ranges are dropped from the set
+    let reply = get_range(addr, "bytes=200-300,0-4").await;
+    assert_eq!(reply.status, 206);
+    assert_eq!(reply.header("Content-Range"), Some("bytes 0-4/100"));
+    assert_eq!(reply.body, data[..5]);
+}
+
+#[tokio::test]
+async fn empty_range_sets_are_ignored() {
+    let dir = TempDir::new("range-empty");
+    let addr = serve(&dir).await;
+
+    for range in ["bytes=", "bytes= ", "bytes=,", "bytes=5-3", "bytes=a-b", "bytes=+1-2", "pages=1-2"] {
+        let reply = get_range(addr, range).await;
+        assert_eq!(reply.status, 200, "{}", range);
+        assert_eq!(reply.body, contents(), "{}", range);
+    }
+}
+
+#[tokio::test]
+async fn ranges_past_the_end_are_not_satisfiable() {
+    let dir = TempDir::new("range-past-end");
+    let addr = serve(&dir).await;
+
+    for range in ["bytes=100-", "bytes=100-200", "bytes=150-160,300-"] {
+        let reply = get_range(addr, range).await;
+        assert_eq!(reply.status, 416, "{}", range);
+        assert_eq!(reply.header("Content-Range"), Some("bytes */100"), "{}", range);
+    }
+}
+
+#[tokio::test]
+async fn overflowing_positions_lie_past_the_end() {
+    let dir = TempDir::new("range-overflow");
+    let addr = serve(&dir).await;
+    let data = contents();
+
+    let reply = get_range(addr, "bytes=10-99999999999999999999999").await;
+    assert_eq!(reply.status, 206);
+    assert_eq!(reply.header("Content-Range"), Some("bytes 10-99/100"));
+    assert_eq!(reply.body, data[10..]);
+
+    let reply = get_range(addr, "bytes=-99999999999999999999999").await;
+    assert_eq!(reply.status, 206);
+    assert_eq!(reply.header("Content-Range"), Some("bytes 0-99/100"));
+
+    let reply = get_range(addr, "bytes=99999999999999999999999-").await;
+    assert_eq!(reply.status, 416);
+
+    let reply = get_range(addr, &format!("bytes={}-{}", u64::MAX, u64::MAX)).await;
+    assert_eq!(reply.status, 416);
+}
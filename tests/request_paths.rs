@@ -0,0 +1,90 @@
+use qsrv::util::{normalize_request_path, percent_decode, percent_encode_path, PathError};
+
+fn normalize(raw: &str) -> Result<String, PathError> {
+    normalize_request_path(raw, false)
+}
+
+#[test]
+fn plain_paths_are_unchanged() {
+    assert_eq!(normalize("/"), Ok("/".to_string()));
+    assert_eq!(normalize("/index.html"), Ok("/index.html".to_string()));
+    assert_eq!(normalize("/docs/"), Ok("/docs/".to_string()));
+    assert_eq!(normalize("/a/b/c.txt"), Ok("/a/b/c.txt".to_string()));
+}
+
+#[test]
+fn percent_escapes_are_decoded() {
+    assert_eq!(normalize("/my%20file.pdf"), Ok("/my file.pdf".to_string()));
+    assert_eq!(normalize("/caf%C3%A9.html"), Ok("/café.html".to_string()));
+    assert_eq!(normalize("/caf%c3%a9.html"), Ok("/café.html".to_string()));
+    assert_eq!(normalize("/100%25.txt"), Ok("/100%.txt".to_string()));
+    assert_eq!(normalize("/a+b.txt"), Ok("/a+b.txt".to_string()));
+}
+
+#[test]
+fn dot_segments_are_resolved() {
+    assert_eq!(normalize("/a/./b"), Ok("/a/b".to_string()));
+    assert_eq!(normalize("/a/b/../c"), Ok("/a/c".to_string()));
+    assert_eq!(normalize("/a/b/.."), Ok("/a/".to_string()));
+    assert_eq!(normalize("/a/."), Ok("/a/".to_string()));
+    assert_eq!(normalize("//a///b"), Ok("/a/b".to_string()));
+}
+
+#[test]
+fn traversal_never_climbs_above_root() {
+    assert_eq!(normalize("/.."), Ok("/".to_string()));
+    assert_eq!(normalize("/../../etc/passwd"), Ok("/etc/passwd".to_string()));
+    assert_eq!(normalize("/a/../../etc/passwd"), Ok("/etc/passwd".to_string()));
+    assert_eq!(normalize("/%2e%2e/%2E%2E/etc/passwd"), Ok("/etc/passwd".to_string()));
+    assert_eq!(normalize("/a/%2e%2e/%2e%2e/b"), Ok("/b".to_string()));
+    assert_eq!(normalize("/.%2e/secret"), Ok("/secret".to_string()));
+}
+
+#[test]
+fn encoded_slashes_are_rejected_by_default() {
+    assert_eq!(normalize("/a%2Fb"), Err(PathError::EncodedSlash));
+    assert_eq!(normalize("/a%2fb"), Err(PathError::EncodedSlash));
+    assert_eq!(normalize("/..%2F..%2Fetc%2Fpasswd"), Err(PathError::EncodedSlash));
+}
+
+#[test]
+fn encoded_slashes_separate_segments_when_allowed() {
+    assert_eq!(normalize_request_path("/a%2Fb", true), Ok("/a/b".to_string()));
+    assert_eq!(normalize_request_path("/..%2F..%2Fetc%2Fpasswd", true), Ok("/etc/passwd".to_string()));
+    assert_eq!(normalize_request_path("/a/b%2F..%2F..%2Fc", true), Ok("/c".to_string()));
+}
+
+#[test]
+fn nul_bytes_are_rejected() {
+    assert_eq!(normalize("/file%00.html"), Err(PathError::Nul));
+    assert_eq!(normalize("/%00"), Err(PathError::Nul));
+    assert_eq!(normalize_request_path("/file%00.html", true), Err(PathError::Nul));
+}
+
+#[test]
+fn malformed_escapes_are_rejected() {
+    assert_eq!(normalize("/bad%"), Err(PathError::InvalidEncoding));
+    assert_eq!(normalize("/bad%2"), Err(PathError::InvalidEncoding));
+    assert_eq!(normalize("/bad%zz"), Err(PathError::InvalidEncoding));
+    assert_eq!(normalize("/bad%+1"), Err(PathError::InvalidEncoding));
+}
+
+#[test]
+fn invalid_utf8_is_rejected() {
+    assert_eq!(normalize("/%C3%28"), Err(PathError::InvalidUtf8));
+    assert_eq!(normalize("/%FF"), Err(PathError::InvalidUtf8));
+}
+
+#[test]
+fn relative_paths_are_rejected() {
+    assert_eq!(normalize("index.html"), Err(PathError::NotAbsolute));
+    assert_eq!(normalize(""), Err(PathError::NotAbsolute));
+}
+
+#[test]
+fn decode_and_encode_round_trip() {
+    assert_eq!(percent_decode("a%20b%2F"), Ok(b"a b/".to_vec()));
+    assert_eq!(percent_encode_path("/my file/café.html"), "/my%20file/caf%C3%A9.html");
+    let encoded = percent_encode_path("/weird name/#?%.txt");
+    assert_eq!(normalize(&encoded), Ok("/weird name/#?%.txt".to_string()));
+}
@@ -0,0 +1,119 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{get, spawn_server, TempDir};
+use qsrv::responders::{FileResolver, ResolverOptions, SymlinkPolicy};
+use std::{net::SocketAddr, os::unix::fs::symlink};
+
+/// Links followed in a row before resolution gives up, as on Linux.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// A document root next to a directory that must stay out of reach, with
+/// links of every kind pointing around and out of the root.
+fn site() -> TempDir {
+    let dir = TempDir::new("symlinks");
+    dir.write("root/sub/a.txt", "inside\n");
+    dir.write("outside/secret.txt", "secret\n");
+    dir.write("outside/dir/b.txt", "outside dir\n");
+
+    let root = dir.path().join("root");
+    symlink("sub/a.txt", root.join("inside")).unwrap();
+    symlink("../outside/secret.txt", root.join("up")).unwrap();
+    symlink(dir.path().join("outside/secret.txt"), root.join("absolute")).unwrap();
+    symlink(std::fs::canonicalize(root.join("sub/a.txt")).unwrap(), root.join("absolute-inside")).unwrap();
+    symlink("../outside/dir", root.join("outdir")).unwrap();
+
+    symlink("sub/a.txt", root.join("short-1")).unwrap();
+    symlink("short-1", root.join("short-0")).unwrap();
+    symlink("sub/a.txt", root.join(format!("chain-{}", MAX_SYMLINK_HOPS + 1))).unwrap();
+    for i in 0..=MAX_SYMLINK_HOPS {
+        symlink(format!("chain-{}", i + 1), root.join(format!("chain-{}", i))).unwrap();
+    }
+
+    dir
+}
+
+async fn serve(dir: &TempDir, symlinks: SymlinkPolicy) -> SocketAddr {
+    let options = ResolverOptions {
+        symlinks,
+        ..ResolverOptions::default()
+    };
+    let root = dir.path().join("root");
+    spawn_server(FileResolver::with_options(root.to_str().unwrap(), options).unwrap(), false).await
+}
+
+async fn status(addr: SocketAddr, path: &str) -> u16 {
+    get(addr, path, &[]).await.status
+}
+
+#[tokio::test]
+async fn follow_reaches_everything() {
+    let dir = site();
+    let addr = serve(&dir, SymlinkPolicy::Follow).await;
+
+    assert_eq!(get(addr, "/inside", &[]).await.body, b"inside\n");
+    assert_eq!(get(addr, "/up", &[]).await.body, b"secret\n");
+    assert_eq!(get(addr, "/absolute", &[]).await.body, b"secret\n");
+    assert_eq!(status(addr, "/absolute-inside").await, 200);
+    assert_eq!(status(addr, "/outdir").await, 301);
+    assert_eq!(get(addr, "/outdir/b.txt", &[]).await.body, b"outside dir\n");
+}
+
+#[tokio::test]
+async fn within_root_refuses_escapes() {
+    let dir = site();
+    let addr = serve(&dir, SymlinkPolicy::WithinRoot).await;
+
+    assert_eq!(get(addr, "/inside", &[]).await.body, b"inside\n");
+    assert_eq!(status(addr, "/absolute-inside").await, 200);
+    assert_eq!(status(addr, "/up").await, 403);
+    assert_eq!(status(addr, "/absolute").await, 403);
+    assert_eq!(status(addr, "/outdir/b.txt").await, 403);
+    // No redirect that would give away the directory outside the root
+    assert_eq!(status(addr, "/outdir").await, 403);
+    assert_eq!(status(addr, "/up/").await, 403);
+}
+
+#[tokio::test]
+async fn never_refuses_every_link() {
+    let dir = site();
+    let addr = serve(&dir, SymlinkPolicy::Never).await;
+
+    assert_eq!(status(addr, "/sub/a.txt").await, 200);
+    assert_eq!(status(addr, "/inside").await, 403);
+    assert_eq!(status(addr, "/absolute-inside").await, 403);
+    assert_eq!(status(addr, "/up").await, 403);
+    assert_eq!(status(addr, "/absolute").await, 403);
+    assert_eq!(status(addr, "/outdir").await, 403);
+    assert_eq!(status(addr, "/outdir/").await, 403);
+}
+
+#[tokio::test]
+async fn owner_match_checks_the_target_owner() {
+    let dir = site();
+    let root = dir.path().join("root");
+    dir.write("root/foreign.txt", "foreign\n");
+    symlink("foreign.txt", root.join("foreign")).unwrap();
+    if std::os::unix::fs::chown(root.join("foreign.txt"), Some(65534), Some(65534)).is_err() {
+        eprintln!("skipping owner mismatch: cannot change file owners");
+        return;
+    }
+    let addr = serve(&dir, SymlinkPolicy::OwnerMatch).await;
+
+    assert_eq!(get(addr, "/inside", &[]).await.body, b"inside\n");
+    assert_eq!(status(addr, "/foreign.txt").await, 200);
+    assert_eq!(status(addr, "/foreign").await, 403);
+}
+
+#[tokio::test]
+async fn long_link_chains_are_cut_off() {
+    let dir = site();
+    for policy in [SymlinkPolicy::Follow, SymlinkPolicy::WithinRoot, SymlinkPolicy::OwnerMatch] {
+        let addr = serve(&dir, policy).await;
+
+        assert_eq!(get(addr, "/short-0", &[]).await.body, b"inside\n", "{:?}", policy);
+        assert_eq!(get(addr, &format!("/chain-{}", MAX_SYMLINK_HOPS - 1), &[]).await.body, b"inside\n", "{:?}", policy);
+        assert_eq!(status(addr, "/chain-0").await, 404, "{:?}", policy);
+    }
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::{get, request, spawn_server, TempDir};
+use qsrv::responders::{FileResolver, ResolverOptions};
+
+/// Larger than the sendfile threshold and than one placeholder frame, so the
+/// body is sent as several file spans.
+const FILE_SIZE: usize = 9 * 1024 * 1024 + 13;
+
+fn contents() -> Vec<u8> {
+    (0..FILE_SIZE).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+async fn zero_copy_server(dir: &TempDir) -> std::net::SocketAddr {
+    let resolver = FileResolver::with_options(dir.to_str(), ResolverOptions::default()).unwrap();
+    spawn_server(resolver, true).await
+}
+
+#[tokio::test]
+async fn large_files_are_sent_intact() {
+    let dir = TempDir::new("zero-copy-full");
+    let data = contents();
+    dir.write("big.bin", &data);
+    let addr = zero_copy_server(&dir).await;
+
+    let reply = get(addr, "/big.bin", &[]).await;
+    assert_eq!(reply.status, 200);
+    assert_eq!(reply.header("Content-Length"), Some(FILE_SIZE.to_string().as_str()));
+    assert_eq!(reply.body.len(), data.len());
+    assert!(reply.body == data, "body differs from the file");
+
+    let reply = request(addr, "HEAD", "/big.bin", &[]).await;
+    assert_eq!(reply.status, 200);
+    assert_eq!(reply.header("Content-Length"), Some(FILE_SIZE.to_string().as_str()));
+    assert!(reply.body.is_empty());
+}
+
+#[tokio::test]
+async fn ranges_of_large_files_are_sent_intact() {
+    let dir = TempDir::new("zero-copy-range");
+    let data = contents();
+    dir.write("big.bin", &data);
+    let addr = zero_copy_server(&dir).await;
+
+    // Straddles the boundary between two placeholder frames
+    let (first, last) = (3 * 1024 * 1024 + 7, 6 * 1024 * 1024 + 3);
+    let reply = get(addr, "/big.bin", &[("Range", &format!("bytes={}-{}", first, last))]).await;
+    assert_eq!(reply.status, 206);
+    assert_eq!(reply.header("Content-Range"), Some(format!("bytes {}-{}/{}", first, last, FILE_SIZE).as_str()));
+    assert!(reply.body == data[first..=last], "range differs from the file");
+
+    let reply = get(addr, "/big.bin", &[("Range", "bytes=-1048577")]).await;
+    assert_eq!(reply.status, 206);
+    assert!(reply.body == data[FILE_SIZE - 1048577..], "suffix differs from the file");
+
+    let reply = get(addr, "/big.bin", &[("Range", "bytes=10-19,8388600-8388609")]).await;
+    assert_eq!(reply.status, 206);
+    assert!(contains(&reply.body, &data[10..20]), "first part differs from the file");
+    assert!(contains(&reply.body, &data[8388600..8388610]), "second part differs from the file");
+}
+
+#[tokio::test]
+async fn small_files_are_sent_intact() {
+    let dir = TempDir::new("zero-copy-small");
+    dir.write("small.txt", "just a few bytes\n");
+    let addr = zero_copy_server(&dir).await;
+
+    let reply = get(addr, "/small.txt", &[]).await;
+    assert_eq!(reply.status, 200);
+    assert_eq!(reply.body, b"just a few bytes\n");
+}